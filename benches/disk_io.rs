@@ -0,0 +1,66 @@
+// Compares synchronous pread/pwrite against io_uring and mmap for the FileDiskManager.
+//
+//     cargo bench --bench disk_io
+
+use buffer_pool::buffer_pool::{DiskManager, FileDiskManager, Page, PageError, MAX_NUM_DISK_PAGES};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 2_000;
+
+fn run(name: &str, open: fn(&Path) -> Result<Box<FileDiskManager>, PageError>) {
+    let path = env::temp_dir().join(format!("buffer_pool_bench_{}.db", name));
+    let _ = fs::remove_file(&path);
+    let mut dm = open(&path).unwrap();
+
+    let mut pages = Vec::new();
+    for _ in 0..MAX_NUM_DISK_PAGES {
+        pages.push(Page::new(dm.allocate_page().unwrap()));
+    }
+    let ops = ROUNDS * pages.len();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for page in pages.iter() {
+            dm.write_page(page).unwrap();
+        }
+    }
+    report(name, "write_page", ops, start.elapsed());
+
+    let mut frame = *Page::new(0);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for page in pages.iter() {
+            dm.read_page(page.id(), &mut frame).unwrap();
+        }
+    }
+    report(name, "read_page", ops, start.elapsed());
+
+    let batch: Vec<&Page> = pages.iter().map(|page| page.as_ref()).collect();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        dm.write_pages(&batch).unwrap();
+    }
+    report(name, "write_pages", ops, start.elapsed());
+
+    fs::remove_file(&path).unwrap();
+}
+
+fn report(name: &str, op: &str, ops: usize, elapsed: Duration) {
+    println!(
+        "{:<8} {:<12} {:>10.0} pages/s",
+        name,
+        op,
+        ops as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    run("pwrite", |path| FileDiskManager::open(path));
+    #[cfg(target_os = "linux")]
+    run("io_uring", |path| FileDiskManager::open_uring(path));
+    #[cfg(target_os = "linux")]
+    run("mmap", |path| FileDiskManager::open_mmap(path, false));
+}
@@ -0,0 +1,118 @@
+use buffer_pool::buffer_pool::{FileDiskManager, PageError, PageId};
+use std::env;
+use std::process;
+use std::time::UNIX_EPOCH;
+
+const USAGE: &str = "usage: bpm-inspect <data file> [--dump <page id>]...";
+
+fn format_ids(ids: &[PageId]) -> String {
+    if ids.is_empty() {
+        return String::from("none");
+    }
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Sixteen bytes per line: offset, hex and printable ASCII.
+fn hex_dump(data: &[u8]) {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|byte| match byte {
+                0x20..=0x7e => *byte as char,
+                _ => '.',
+            })
+            .collect();
+        println!("  {:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii);
+    }
+}
+
+fn dump(path: &str, id: PageId) -> Result<(), PageError> {
+    let slot = FileDiskManager::dump_page(path, id)?;
+    println!("page {}:", id);
+    hex_dump(&slot.data);
+    let status = if slot.stored_checksum == slot.checksum {
+        "ok"
+    } else {
+        "mismatch"
+    };
+    println!(
+        "  checksum {:08x}, expected {:08x} ({})",
+        slot.stored_checksum, slot.checksum, status
+    );
+    Ok(())
+}
+
+// Exits with 1 when the file has problems or can't be checked, and 2 on bad arguments.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.first() {
+        Some(path) if !path.starts_with("--") => path.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let mut dumps = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match (arg.as_str(), rest.next().map(|id| id.parse::<PageId>())) {
+            ("--dump", Some(Ok(id))) => dumps.push(id),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let report = match FileDiskManager::check(&path) {
+        Ok(report) => report,
+        Err(PageError::UnsupportedVersion { found }) => {
+            eprintln!(
+                "{}: format version {} isn't supported, upgrade the file with FileDiskManager::upgrade",
+                path, found
+            );
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}: unable to check data file: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    let file_header = report.file_header;
+    let created = file_header
+        .created
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    println!(
+        "format version {}, page size {}, flags {:#x}, created at {}",
+        file_header.version, file_header.page_size, file_header.flags, created
+    );
+    // Without a readable header page there is no allocation map to list.
+    if report.allocated.is_empty() && report.free.is_empty() {
+        println!("allocation map unreadable");
+    } else {
+        println!("allocated pages: {}", format_ids(&report.allocated));
+        println!("free pages: {}", format_ids(&report.free));
+    }
+
+    let mut failed = !report.is_consistent();
+    for (id, problem) in report.problems.iter() {
+        println!("page {}: {}", id, problem);
+    }
+    for id in dumps {
+        if let Err(e) = dump(&path, id) {
+            eprintln!("page {}: unable to dump: {}", id, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    println!("no problems found");
+}
@@ -1,18 +1,62 @@
+mod arc_replacer;
 mod disk_manager_mock;
+mod double_write_disk_manager;
 mod clock_replacer;
+mod file_disk_manager;
+mod file_io;
+mod instrumented_disk_manager;
+#[cfg(test)]
+mod local_object_server;
+mod lru_k_replacer;
+mod lru_replacer;
+mod mirrored_disk_manager;
+mod object_store_disk_manager;
+mod faulty_disk_manager;
+mod simulated_disk_manager;
+mod tablespace_disk_manager;
+mod two_queue_replacer;
+mod versioned_disk_manager;
+mod shadow_disk_manager;
 mod page;
 
 use crate::buffer_pool::PageError::{PageNotFound, PageStillInUse, PoolExhausted};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, VecDeque};
-use crate::buffer_pool::page::PageError;
-use crate::buffer_pool::clock_replacer::{ClockReplacer, ClockReplacerRep};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+pub use crate::buffer_pool::page::PageError;
+pub use crate::buffer_pool::arc_replacer::{ArcReplacer, ArcReplacerRep};
+pub use crate::buffer_pool::clock_replacer::{ClockReplacer, ClockReplacerRep};
+pub use crate::buffer_pool::file_disk_manager::{
+    CheckReport, FileDiskManager, FileHeader, SlotDump, SpaceUsage, FLAG_BLOCK_SLOTS,
+    FLAG_PAGE_CHECKSUMS, FORMAT_VERSION,
+};
+pub use crate::buffer_pool::double_write_disk_manager::DoubleWriteDiskManager;
+pub use crate::buffer_pool::faulty_disk_manager::{FaultRule, FaultyDiskManager, Operation};
+pub use crate::buffer_pool::instrumented_disk_manager::{
+    InstrumentedDiskManager, IoStats, LatencyHistogram,
+};
+pub use crate::buffer_pool::lru_k_replacer::{LruKFrame, LruKReplacer, LruKReplacerRep};
+pub use crate::buffer_pool::lru_replacer::{LruReplacer, LruReplacerRep};
+pub use crate::buffer_pool::mirrored_disk_manager::MirroredDiskManager;
+pub use crate::buffer_pool::object_store_disk_manager::ObjectStoreDiskManager;
+pub use crate::buffer_pool::shadow_disk_manager::ShadowDiskManager;
+pub use crate::buffer_pool::simulated_disk_manager::{
+    DeviceProfile, OpStats, SimulatedDiskManager, SimulatedStats,
+};
+pub use crate::buffer_pool::tablespace_disk_manager::{
+    TablespaceDiskManager, MAX_SEGMENTS_PER_TABLESPACE, SEGMENT_NUM_PAGES,
+};
+pub use crate::buffer_pool::two_queue_replacer::{TwoQueueReplacer, TwoQueueReplacerRep};
+pub use crate::buffer_pool::versioned_disk_manager::VersionedDiskManager;
 use std::any::Any;
 
 pub const MAX_POOL_SIZE: usize = 4;
 pub const MAX_NUM_DISK_PAGES: i32 = 15;
 pub const PAGE_SIZE: usize = 8;
 
+// Marks a frame that doesn't hold a page.
+const INVALID_PAGE_ID: PageId = -1;
+
 pub type FrameId = i32;
 pub type PageId = i32;
 
@@ -28,45 +72,185 @@ pub trait Replacer {
     fn victim(&mut self) -> Option<FrameId>;
     fn unpin(&mut self, id: FrameId);
     fn pin(&mut self, id: FrameId);
+    // Called every time the page in a frame is used: when it is created, read from disk or
+    // fetched while already in the pool. A different page id than last time means the frame
+    // holds a new page.
+    fn record_access(&mut self, _id: FrameId, _page_id: PageId) {}
+    // Called when the page in a frame is deleted and the frame goes back to the free list.
+    fn remove(&mut self, id: FrameId) {
+        self.pin(id);
+    }
+    // Called when a page is deleted, whether or not it is in a frame, as its id can be handed
+    // out again for an unrelated page.
+    fn forget_page(&mut self, _page_id: PageId) {}
+    // Called with the `(old id, new id)` of every page moved by `compact`, all at once.
+    fn remap(&mut self, _moves: &[(PageId, PageId)]) {}
+    fn response(&self) -> ReplacerRep;
+}
+
+// The state of the replacer in a `Response`, under a key named after the replacer. Any
+// serializable state will do, so replacers outside this crate can report their own.
+#[derive(Deserialize, Serialize)]
+pub struct ReplacerRep {
+    #[serde(flatten)]
+    state: BTreeMap<String, serde_json::Value>,
+}
+
+impl ReplacerRep {
+    pub fn new<T: Serialize>(name: &str, state: &T) -> ReplacerRep {
+        let state = serde_json::to_value(state).expect("replacer state must serialize to JSON");
+        let mut rep = ReplacerRep { state: BTreeMap::new() };
+        rep.state.insert(name.to_string(), state);
+        rep
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.state.keys().next().map(String::as_str)
+    }
+
+    // The state reported under `name`, if any, as a replacer's own representation type.
+    pub fn state<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        serde_json::from_value(self.state.get(name)?.clone()).ok()
+    }
 }
 
 pub trait DiskManager {
-    fn read_page(&mut self, id: PageId) -> Result<&Box<Page>, PageError>;
-    fn write_page(&mut self, page: &Box<Page>) -> Result<(), PageError>;
+    // Fills the id and data of `page`, typically a frame owned by the buffer pool. Its pin count
+    // and dirty flag are left alone.
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError>;
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError>;
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        for page in pages {
+            self.write_page(page)?;
+        }
+        Ok(())
+    }
+    fn read_pages(&mut self, ids: &[PageId], pages: &mut [Page]) -> Result<(), PageError> {
+        for (id, page) in ids.iter().zip(pages.iter_mut()) {
+            self.read_page(*id, page)?;
+        }
+        Ok(())
+    }
+    // Writes only the first `bytes` of the page as it is stored, leaving the rest as it was, like
+    // a crash in the middle of the write would. Only used to inject torn writes.
+    fn write_page_torn(&mut self, page: &Page, bytes: usize) -> Result<(), PageError> {
+        let mut torn = *page;
+        if self.read_page(page.id, &mut torn).is_err() {
+            torn.data = [0; PAGE_SIZE];
+        }
+        let bytes = bytes.min(PAGE_SIZE);
+        torn.data[..bytes].copy_from_slice(&page.data[..bytes]);
+        self.write_page(&torn)
+    }
+    // Makes everything written so far durable.
+    fn sync(&mut self) -> Result<(), PageError> {
+        Ok(())
+    }
     fn allocate_page(&mut self) -> Result<PageId, PageError>;
-    fn deallocate_page(&mut self, id: PageId);
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError>;
+    // Moves live pages to the lowest free page ids so the storage can shrink, and returns the
+    // `(old id, new id)` of every page that moved. Anything referring to pages by id must be
+    // updated with it.
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        Ok(Vec::new())
+    }
     fn pages_on_disk(&self) -> Vec<i32>;
+    // The header page holding the allocation map, for disk managers that keep one. It is
+    // rewritten by `allocate_page` and `deallocate_page`.
+    fn header_page(&self) -> Option<Page> {
+        None
+    }
     fn as_any(&self) -> &dyn Any;
 }
 
+impl<D: DiskManager + ?Sized> DiskManager for Box<D> {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        (**self).read_page(id, page)
+    }
+
+    fn read_pages(&mut self, ids: &[PageId], pages: &mut [Page]) -> Result<(), PageError> {
+        (**self).read_pages(ids, pages)
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        (**self).write_page(page)
+    }
+
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        (**self).write_pages(pages)
+    }
+
+    fn write_page_torn(&mut self, page: &Page, bytes: usize) -> Result<(), PageError> {
+        (**self).write_page_torn(page, bytes)
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        (**self).sync()
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        (**self).allocate_page()
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        (**self).deallocate_page(id)
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        (**self).compact()
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        (**self).pages_on_disk()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        (**self).header_page()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        (**self).as_any()
+    }
+}
+
 pub struct DiskManagerMock {
     num_pages: i32,
-    pages: HashMap<PageId, Box<Page>>,
+    free_pages: BTreeSet<PageId>,
+    pages: HashMap<PageId, [u8; PAGE_SIZE]>,
 }
 
 impl DiskManagerMock {
     pub fn new() -> Box<DiskManagerMock> {
         Box::new(DiskManagerMock {
             num_pages: 0,
+            free_pages: BTreeSet::new(),
             pages: HashMap::new(),
         })
     }
 }
 
 pub struct BufferPoolManager {
-    disk_manager: Box<dyn DiskManager + Send>,
-    replacer: ClockReplacer,
-    pages: Vec<Option<Box<Page>>>,
+    disk_manager: Box<InstrumentedDiskManager<Box<dyn DiskManager + Send>>>,
+    replacer: Box<dyn Replacer + Send>,
+    // Frames are allocated once and reused in place for every page that passes through them.
+    pages: Vec<Page>,
     free_list: VecDeque<FrameId>,
     page_table: HashMap<PageId, FrameId>,
 }
 
 impl BufferPoolManager {
     pub fn new(disk_manager: Box<dyn DiskManager + Send>) -> BufferPoolManager {
+        BufferPoolManager::with_replacer(disk_manager, Box::new(ClockReplacer::new()))
+    }
+
+    pub fn with_replacer(
+        disk_manager: Box<dyn DiskManager + Send>,
+        replacer: Box<dyn Replacer + Send>,
+    ) -> BufferPoolManager {
         let mut manager = BufferPoolManager {
-            disk_manager,
-            replacer: ClockReplacer::new(),
-            pages: vec![None; MAX_POOL_SIZE],
+            disk_manager: InstrumentedDiskManager::new(disk_manager),
+            replacer,
+            pages: vec![*Page::new(INVALID_PAGE_ID); MAX_POOL_SIZE],
             free_list: VecDeque::new(),
             page_table: HashMap::new(),
         };
@@ -81,20 +265,24 @@ impl BufferPoolManager {
             Ok((frame_id, is_from_free_list)) => {
                 if !is_from_free_list {
                     if let Err(e) = self.write_if_dirty(frame_id) {
+                        self.replacer.unpin(frame_id);
                         return Err(e);
                     }
                 }
                 match self.disk_manager.allocate_page() {
                     Ok(page_id) => {
                         self.page_table.insert(page_id, frame_id);
-                        self.pages[frame_id as usize] = Some(Page::new(page_id));
-                        if let Some(page) = self.pages[frame_id as usize].as_mut() {
-                            Ok(page)
-                        } else {
-                            panic!("not possible!")
-                        }
+                        self.replacer.record_access(frame_id, page_id);
+                        let page = &mut self.pages[frame_id as usize];
+                        page.reset(page_id);
+                        // Nothing has been written for the page yet.
+                        page.is_dirty = true;
+                        Ok(page)
+                    }
+                    Err(e) => {
+                        self.free_list.push_back(frame_id);
+                        Err(e)
                     }
-                    Err(e) => Err(e),
                 }
             }
             Err(e) => Err(e),
@@ -103,33 +291,34 @@ impl BufferPoolManager {
 
     pub fn fetch_page(&mut self, id: PageId) -> Result<&mut Page, PageError> {
         if let Some(frame_id) = self.page_table.get(&id) {
-            if let Some(page) = self.pages[*frame_id as usize].as_mut() {
-                page.pin_count += 1;
-                self.replacer.pin(*frame_id);
-                Ok(page)
-            } else {
-                panic!("not possible!")
-            }
+            let page = &mut self.pages[*frame_id as usize];
+            page.pin_count += 1;
+            self.replacer.pin(*frame_id);
+            self.replacer.record_access(*frame_id, id);
+            Ok(page)
         } else {
             match self.get_frame_id() {
                 Ok((frame_id, is_from_free_list)) => {
                     if !is_from_free_list {
                         if let Err(e) = self.write_if_dirty(frame_id) {
+                            self.replacer.unpin(frame_id);
                             return Err(e);
                         }
                     }
-                    match self.disk_manager.read_page(id) {
-                        Ok(page) => {
+                    let page = &mut self.pages[frame_id as usize];
+                    match self.disk_manager.read_page(id, page) {
+                        Ok(()) => {
                             self.page_table.insert(id, frame_id);
-                            self.pages[frame_id as usize] = Some(page.clone());
-                            if let Some(page) = self.pages[frame_id as usize].as_mut() {
-                                page.pin_count = 1;
-                                Ok(page)
-                            } else {
-                                panic!("not possible!")
-                            }
+                            self.replacer.record_access(frame_id, id);
+                            page.pin_count = 1;
+                            page.is_dirty = false;
+                            Ok(page)
+                        }
+                        Err(e) => {
+                            page.id = INVALID_PAGE_ID;
+                            self.free_list.push_back(frame_id);
+                            Err(e)
                         }
-                        Err(e) => Err(e),
                     }
                 }
                 Err(e) => Err(e),
@@ -139,14 +328,11 @@ impl BufferPoolManager {
 
     pub fn unpin_page(&mut self, id: PageId, is_dirty: bool) -> Result<(), PageError> {
         if let Some(frame_id) = self.page_table.get(&id) {
-            if let Some(page) = self.pages[*frame_id as usize].as_mut() {
-                if page.dec_pin_count() {
-                    self.replacer.unpin(*frame_id);
-                }
-                page.is_dirty = page.is_dirty || is_dirty;
-            } else {
-                panic!("not possible!")
+            let page = &mut self.pages[*frame_id as usize];
+            if page.dec_pin_count() {
+                self.replacer.unpin(*frame_id);
             }
+            page.is_dirty = page.is_dirty || is_dirty;
             Ok(())
         } else {
             Err(PageNotFound)
@@ -155,15 +341,10 @@ impl BufferPoolManager {
 
     pub fn flush_page(&mut self, id: PageId) -> Result<(), PageError> {
         if let Some(frame_id) = self.page_table.get(&id) {
-            if let Some(page) = self.pages[*frame_id as usize].as_mut() {
-                // page.dec_pin_count(); // In the original, but it might be a defect?
-                if let Err(e) = self.disk_manager.write_page(page) {
-                    return Err(e);
-                }
-                page.is_dirty = false;
-            } else {
-                panic!("not possible!")
-            }
+            let page = &mut self.pages[*frame_id as usize];
+            // page.dec_pin_count(); // In the original, but it might be a defect?
+            self.disk_manager.write_page(page)?;
+            page.is_dirty = false;
             Ok(())
         } else {
             Err(PageNotFound)
@@ -171,36 +352,67 @@ impl BufferPoolManager {
     }
 
     pub fn flush_all_pages(&mut self) -> Result<(), PageError> {
-        for maybe_page in self.pages.iter_mut() {
-            if let Some(page) = maybe_page {
-                // page.dec_pin_count(); // In the original, but it might be a defect?
-                if let Err(e) = self.disk_manager.write_page(page) {
-                    return Err(e);
-                }
-                page.is_dirty = false;
-            }
+        // Only dirty pages, in disk order, so the disk manager can coalesce adjacent writes.
+        let mut pages: Vec<&Page> = self
+            .pages
+            .iter()
+            .filter(|page| page.id != INVALID_PAGE_ID && page.is_dirty)
+            .collect();
+        pages.sort_by_key(|page| page.id);
+        self.disk_manager.write_pages(&pages)?;
+        for page in self.pages.iter_mut() {
+            // page.dec_pin_count(); // In the original, but it might be a defect?
+            page.is_dirty = false;
         }
         Ok(())
     }
 
+    // Flushes every dirty page and makes the writes durable. For a `ShadowDiskManager` this
+    // commits every change since the previous sync at once.
+    pub fn sync(&mut self) -> Result<(), PageError> {
+        self.flush_all_pages()?;
+        self.disk_manager.sync()
+    }
+
     pub fn delete_page(&mut self, id: PageId) -> Result<(), PageError> {
         if let Some(frame_id) = self.page_table.get(&id) {
-            if let Some(page) = self.pages[*frame_id as usize].as_mut() {
-                if page.pin_count > 0 {
-                    return Err(PageStillInUse);
-                }
-                self.replacer.pin(*frame_id);
-                self.disk_manager.deallocate_page(id);
-                self.free_list.push_back(*frame_id);
-
-                self.page_table.remove(&id);
-            } else {
-                panic!("not possible!")
+            let frame_id = *frame_id;
+            let page = &mut self.pages[frame_id as usize];
+            if page.pin_count > 0 {
+                return Err(PageStillInUse);
             }
-            Ok(())
+            self.disk_manager.deallocate_page(id)?;
+            page.id = INVALID_PAGE_ID;
+            self.replacer.remove(frame_id);
+            self.free_list.push_back(frame_id);
+
+            self.page_table.remove(&id);
         } else {
-            Err(PageNotFound)
+            self.disk_manager.deallocate_page(id)?;
         }
+        self.replacer.forget_page(id);
+        Ok(())
+    }
+
+    // Flushes all pages and compacts the disk manager. Resident pages stay in their frames under
+    // their new ids, so no page may be pinned. Returns the `(old id, new id)` of every page that
+    // moved.
+    pub fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        if self.pages.iter().any(|page| page.pin_count > 0) {
+            return Err(PageStillInUse);
+        }
+        self.flush_all_pages()?;
+        let moves = self.disk_manager.compact()?;
+        let mut moved_frames = Vec::new();
+        for (old_id, new_id) in moves.iter() {
+            if let Some(frame_id) = self.page_table.remove(old_id) {
+                self.pages[frame_id as usize].id = *new_id;
+                moved_frames.push((*new_id, frame_id));
+            }
+        }
+        self.page_table.extend(moved_frames);
+        self.replacer.remap(&moves);
+        Ok(moves)
     }
 
     fn get_frame_id(&mut self) -> Result<(FrameId, bool), PageError> {
@@ -219,14 +431,17 @@ impl BufferPoolManager {
         }
     }
 
+    // Writes out the page held by a victim frame and empties the frame. On failure the page stays
+    // in the frame so nothing is lost.
     fn write_if_dirty(&mut self, frame_id: FrameId) -> Result<(), PageError> {
-        let mut existing_page: Option<Box<Page>> = None;
-        std::mem::swap(&mut self.pages[frame_id as usize], &mut existing_page);
-        if let Some(page) = existing_page {
+        let page = &mut self.pages[frame_id as usize];
+        if page.id != INVALID_PAGE_ID {
             if page.is_dirty {
-                return self.disk_manager.write_page(&page);
+                self.disk_manager.write_page(page)?;
+                page.is_dirty = false;
             }
             self.page_table.remove(&page.id);
+            page.id = INVALID_PAGE_ID;
         }
         Ok(())
     }
@@ -240,37 +455,47 @@ pub struct Response {
     pub max_pool_size: i32,
     #[serde(rename = "PagesTable")]
     pub page_table: HashMap<PageId, FrameId>,
-    #[serde(rename = "ClockReplacer")]
-    pub clock_replacer: ClockReplacerRep,
+    #[serde(flatten)]
+    pub replacer: ReplacerRep,
     #[serde(rename = "MaxDiskNumPages")]
     pub max_disk_num_pages: i32,
     #[serde(rename = "PinCount")]
     pub pin_count: HashMap<i32, i32>,
+    #[serde(rename = "IoStats")]
+    pub io_stats: IoStats,
 }
 
 impl BufferPoolManager {
     pub fn response(&self) -> Response {
         let mut pin_count: HashMap<PageId, i32> = HashMap::new();
-        for page in self.pages.iter() {
-            if let Some(page) = page {
-                pin_count.insert(page.id, page.pin_count);
-            }
+        for page in self.pages.iter().filter(|page| page.id != INVALID_PAGE_ID) {
+            pin_count.insert(page.id, page.pin_count);
         }
         Response {
             pages_in_disk: self.disk_manager.pages_on_disk(),
             max_pool_size: MAX_POOL_SIZE as i32,
             page_table: self.page_table.clone(),
-            clock_replacer: self.replacer.response(),
+            replacer: self.replacer.response(),
             max_disk_num_pages: MAX_NUM_DISK_PAGES,
             pin_count,
+            io_stats: self.disk_manager.stats().clone(),
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::buffer_pool::{BufferPoolManager, DiskManagerMock, MAX_POOL_SIZE, DiskManager};
-    use crate::buffer_pool::page::PageError::PoolExhausted;
+pub(crate) mod tests {
+    use crate::buffer_pool::{BufferPoolManager, DiskManagerMock, MAX_POOL_SIZE, DiskManager, MAX_NUM_DISK_PAGES, INVALID_PAGE_ID};
+    use crate::buffer_pool::page::PageError::{IoError, PageNotFound, PageStillInUse, PoolExhausted};
+    use crate::buffer_pool::{DeviceProfile, FaultyDiskManager, Operation, Page, PageError, PageId, SimulatedDiskManager};
+    use crate::buffer_pool::{ClockReplacerRep, FrameId, Replacer, ReplacerRep, Response};
+    use crate::buffer_pool::file_disk_manager::slot_offset;
+    use std::env;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::{ErrorKind, Seek, SeekFrom, Write};
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn unpin_page() {
@@ -278,7 +503,7 @@ mod tests {
 
         all_pages(&mut bpm);
         bpm.unpin_page(1, false).unwrap();
-        assert_eq!(0, bpm.pages[0].as_ref().unwrap().pin_count);
+        assert_eq!(0, bpm.pages[0].pin_count);
 
         let page_id = bpm.new_page().unwrap().id;
         assert_eq!(MAX_POOL_SIZE as i32 + 1, page_id);
@@ -291,12 +516,12 @@ mod tests {
     fn flush_page() {
         let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
 
-       assert_eq!(0, as_mock(&bpm.disk_manager).pages.len());
+       assert_eq!(0, as_mock(bpm.disk_manager.inner().as_ref()).pages.len());
 
         let page_id = bpm.new_page().unwrap().id;
         bpm.flush_page(page_id).unwrap();
 
-        assert!(as_mock(&bpm.disk_manager).pages.contains_key(&page_id))
+        assert!(as_mock(bpm.disk_manager.inner().as_ref()).pages.contains_key(&page_id))
     }
 
     #[test]
@@ -305,18 +530,18 @@ mod tests {
 
         all_pages(&mut bpm);
         bpm.unpin_page(1, false).unwrap();
-        assert_eq!(1, bpm.pages[0].as_ref().unwrap().id);
-        assert_eq!(0, bpm.pages[0].as_ref().unwrap().pin_count);
+        assert_eq!(1, bpm.pages[0].id);
+        assert_eq!(0, bpm.pages[0].pin_count);
         bpm.flush_page(1).unwrap();
 
         bpm.new_page().unwrap();
         bpm.unpin_page(5, false).unwrap();
-        assert_eq!(5, bpm.pages[0].as_ref().unwrap().id);
-        assert_eq!(0, bpm.pages[0].as_ref().unwrap().pin_count);
+        assert_eq!(5, bpm.pages[0].id);
+        assert_eq!(0, bpm.pages[0].pin_count);
 
         bpm.fetch_page(1).unwrap();
-        assert_eq!(1, bpm.pages[0].as_ref().unwrap().id);
-        assert_eq!(1, bpm.pages[0].as_ref().unwrap().pin_count);
+        assert_eq!(1, bpm.pages[0].id);
+        assert_eq!(1, bpm.pages[0].pin_count);
     }
 
     #[test]
@@ -327,13 +552,40 @@ mod tests {
         assert!(bpm.page_table.contains_key(&1));
 
         bpm.flush_page(1).unwrap();
-        assert!(as_mock(&bpm.disk_manager).pages.contains_key(&1));
+        assert!(as_mock(bpm.disk_manager.inner().as_ref()).pages.contains_key(&1));
 
         bpm.unpin_page(1, false).unwrap();
 
         bpm.delete_page(1).unwrap();
         assert!(!bpm.page_table.contains_key(&1));
-        assert!(!as_mock(&bpm.disk_manager).pages.contains_key(&1));
+        assert!(!as_mock(bpm.disk_manager.inner().as_ref()).pages.contains_key(&1));
+    }
+
+    #[test]
+    fn delete_evicted_page() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+
+        all_pages(&mut bpm);
+        bpm.flush_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        bpm.new_page().unwrap();
+        assert!(!bpm.page_table.contains_key(&1));
+
+        bpm.delete_page(1).unwrap();
+        assert!(!as_mock(bpm.disk_manager.inner().as_ref()).pages.contains_key(&1));
+        assert_eq!(PageNotFound, bpm.delete_page(1).unwrap_err());
+    }
+
+    #[test]
+    fn reuse_deleted_page_ids() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+
+        for _ in 0..MAX_NUM_DISK_PAGES * 2 {
+            let page_id = bpm.new_page().unwrap().id;
+            assert_eq!(1, page_id);
+            bpm.unpin_page(page_id, false).unwrap();
+            bpm.delete_page(page_id).unwrap();
+        }
     }
 
     #[test]
@@ -341,16 +593,285 @@ mod tests {
         let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
 
         all_pages(&mut bpm);
-        assert_eq!(0, as_mock(&bpm.disk_manager).pages.len());
+        assert_eq!(0, as_mock(bpm.disk_manager.inner().as_ref()).pages.len());
 
         bpm.flush_all_pages().unwrap();
 
         for i in 0..MAX_POOL_SIZE as i32 {
-            assert!(as_mock(&bpm.disk_manager).pages.contains_key(&(i + 1)));
+            assert!(as_mock(bpm.disk_manager.inner().as_ref()).pages.contains_key(&(i + 1)));
         }
     }
 
-    fn as_mock(dm: &Box<dyn DiskManager + Send>) -> &DiskManagerMock {
+    #[test]
+    fn flush_page_error() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Write).times(1);
+        let mut bpm = BufferPoolManager::new(dm);
+
+        let page_id = bpm.new_page().unwrap().id;
+        bpm.unpin_page(page_id, true).unwrap();
+        assert_eq!(IoError(ErrorKind::Other), bpm.flush_page(page_id).unwrap_err());
+        assert!(bpm.pages[0].is_dirty);
+
+        bpm.flush_page(page_id).unwrap();
+        assert!(!bpm.pages[0].is_dirty);
+    }
+
+    #[test]
+    fn flush_all_pages_error() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Write).after(2).times(1);
+        let mut bpm = BufferPoolManager::new(dm);
+
+        all_pages(&mut bpm);
+        assert_eq!(IoError(ErrorKind::Other), bpm.flush_all_pages().unwrap_err());
+        bpm.flush_all_pages().unwrap();
+    }
+
+    #[test]
+    fn evict_dirty_page_error() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Write).on_page(1).times(1);
+        let mut bpm = BufferPoolManager::new(dm);
+
+        all_pages(&mut bpm);
+        bpm.unpin_page(1, true).unwrap();
+
+        assert_eq!(IoError(ErrorKind::Other), bpm.new_page().unwrap_err());
+        assert_eq!(0, *bpm.page_table.get(&1).unwrap());
+        assert_eq!(1, bpm.pages[0].id);
+        assert!(bpm.pages[0].is_dirty);
+
+        let page_id = bpm.new_page().unwrap().id;
+        assert_eq!(0, *bpm.page_table.get(&page_id).unwrap());
+        assert!(!bpm.page_table.contains_key(&1));
+        bpm.unpin_page(page_id, false).unwrap();
+        assert_eq!(1, bpm.fetch_page(1).unwrap().id);
+    }
+
+    #[test]
+    fn allocate_page_error() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Allocate).times(MAX_POOL_SIZE);
+        let mut bpm = BufferPoolManager::new(dm);
+
+        for _ in 0..MAX_POOL_SIZE {
+            assert_eq!(IoError(ErrorKind::Other), bpm.new_page().unwrap_err());
+        }
+        all_pages(&mut bpm);
+    }
+
+    #[test]
+    fn flush_all_pages_writes_dirty_pages() {
+        let mut bpm = BufferPoolManager::new(SimulatedDiskManager::new(
+            DiskManagerMock::new(),
+            DeviceProfile::nvme(),
+        ));
+        let writes = |bpm: &BufferPoolManager| {
+            bpm.disk_manager
+                .inner()
+                .as_any()
+                .downcast_ref::<SimulatedDiskManager<Box<DiskManagerMock>>>()
+                .unwrap()
+                .stats()
+                .writes
+                .count
+        };
+
+        all_pages(&mut bpm);
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(MAX_POOL_SIZE as u64, writes(&bpm));
+
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(MAX_POOL_SIZE as u64, writes(&bpm));
+
+        bpm.unpin_page(2, true).unwrap();
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(MAX_POOL_SIZE as u64 + 1, writes(&bpm));
+    }
+
+    #[test]
+    fn read_pages() {
+        let mut dm = DiskManagerMock::new();
+        for _ in 0..3 {
+            let id = dm.allocate_page().unwrap();
+            let mut page = Page::new(id);
+            page.data = [id as u8; 8];
+            dm.write_page(&page).unwrap();
+        }
+
+        let mut pages = [*Page::new(0); 2];
+        dm.read_pages(&[3, 1], &mut pages).unwrap();
+        assert_eq!(vec![(3, [3; 8]), (1, [1; 8])], pages.iter().map(|page| (page.id, page.data)).collect::<Vec<_>>());
+        assert_eq!(PageNotFound, dm.read_pages(&[1, 4], &mut pages).unwrap_err());
+    }
+
+    #[test]
+    fn fetch_missing_page() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+
+        let page_id = bpm.new_page().unwrap().id;
+        bpm.flush_page(page_id).unwrap();
+        bpm.unpin_page(page_id, false).unwrap();
+        bpm.delete_page(page_id).unwrap();
+        assert_eq!(INVALID_PAGE_ID, bpm.pages[0].id);
+
+        assert_eq!(PageNotFound, bpm.fetch_page(page_id).unwrap_err());
+        assert_eq!(INVALID_PAGE_ID, bpm.pages[1].id);
+        assert!(bpm.response().pin_count.is_empty());
+        assert_eq!(MAX_POOL_SIZE, bpm.free_list.len());
+    }
+
+    #[test]
+    fn io_stats() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+
+        all_pages(&mut bpm);
+        bpm.unpin_page(1, false).unwrap();
+        bpm.new_page().unwrap();
+        bpm.unpin_page(5, false).unwrap();
+        bpm.fetch_page(1).unwrap();
+        bpm.fetch_page(1).unwrap();
+
+        let stats = bpm.response().io_stats;
+        assert_eq!(MAX_POOL_SIZE as u64 + 1, stats.allocations);
+        assert_eq!(2, stats.writes);
+        assert_eq!(1, stats.reads);
+        assert_eq!(0, stats.errors);
+        assert_eq!(stats, *bpm.disk_manager.stats());
+    }
+
+    #[test]
+    fn compact() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+
+        all_pages(&mut bpm);
+        for i in 1..=MAX_POOL_SIZE as i32 {
+            bpm.fetch_page(i).unwrap().data = [i as u8; 8];
+            bpm.unpin_page(i, true).unwrap();
+            bpm.unpin_page(i, false).unwrap();
+        }
+        bpm.delete_page(1).unwrap();
+        bpm.delete_page(3).unwrap();
+
+        bpm.fetch_page(2).unwrap();
+        assert_eq!(PageStillInUse, bpm.compact().unwrap_err());
+        bpm.unpin_page(2, false).unwrap();
+
+        assert_eq!(vec![(2, 1), (4, 2)], bpm.compact().unwrap());
+        assert_eq!(vec![1, 2], bpm.disk_manager.pages_on_disk());
+        assert_eq!(2, bpm.page_table.len());
+        assert_eq!([2; 8], bpm.fetch_page(1).unwrap().data);
+        assert_eq!([4; 8], bpm.fetch_page(2).unwrap().data);
+        assert_eq!(3, bpm.new_page().unwrap().id);
+    }
+
+    // Evicts the most recently unpinned frame first.
+    struct StackReplacer {
+        frames: Vec<FrameId>,
+    }
+
+    impl Replacer for StackReplacer {
+        fn victim(&mut self) -> Option<FrameId> {
+            self.frames.pop()
+        }
+
+        fn unpin(&mut self, id: FrameId) {
+            self.pin(id);
+            self.frames.push(id);
+        }
+
+        fn pin(&mut self, id: FrameId) {
+            self.frames.retain(|frame_id| *frame_id != id);
+        }
+
+        fn response(&self) -> ReplacerRep {
+            ReplacerRep::new("StackReplacer", &self.frames)
+        }
+    }
+
+    #[test]
+    fn with_replacer() {
+        let replacer = Box::new(StackReplacer { frames: Vec::new() });
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+
+        all_pages(&mut bpm);
+        for i in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(i, false).unwrap();
+        }
+        assert_eq!(5, bpm.new_page().unwrap().id);
+        assert_eq!(5, bpm.pages[3].id);
+
+        let json: serde_json::Value = serde_json::to_value(bpm.response()).unwrap();
+        assert_eq!(serde_json::json!([0, 1, 2]), json["StackReplacer"]);
+    }
+
+    #[test]
+    fn response_json() {
+        let mut bpm = BufferPoolManager::new(DiskManagerMock::new());
+        bpm.new_page().unwrap();
+        bpm.unpin_page(1, false).unwrap();
+
+        let json: serde_json::Value = serde_json::to_value(bpm.response()).unwrap();
+        assert_eq!(0, json["ClockReplacer"]["ClockHand"]);
+        assert_eq!(0, json["ClockReplacer"]["Clock"][0]["ClockFrame"]);
+        let response: Response = serde_json::from_value(json).unwrap();
+        assert_eq!(Some("ClockReplacer"), response.replacer.name());
+        let rep: ClockReplacerRep = response.replacer.state("ClockReplacer").unwrap();
+        assert_eq!(1, rep.clock.len());
+    }
+
+    // A file in the temp directory that is removed again when dropped, even if the test fails.
+    pub(crate) struct DataFile(PathBuf);
+
+    impl Deref for DataFile {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for DataFile {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for DataFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    pub(crate) fn data_file(name: &str) -> DataFile {
+        let path = env::temp_dir().join(format!("buffer_pool_{}_{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        DataFile(path)
+    }
+
+    // Flips a byte of a page in a `FileDiskManager` data file, so it fails its checksum.
+    pub(crate) fn corrupt_page(path: &Path, id: PageId) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(slot_offset(id) + 3)).unwrap();
+        file.write_all(&[0xff]).unwrap();
+    }
+
+    // A page holding `data`, to hand to `write_page`.
+    pub(crate) fn page(id: PageId, data: &[u8; 8]) -> Box<Page> {
+        let mut page = Page::new(id);
+        page.data.copy_from_slice(data);
+        page
+    }
+
+    // Reads a page into a fresh frame.
+    pub(crate) fn read<D: DiskManager + ?Sized>(dm: &mut D, id: PageId) -> Result<Page, PageError> {
+        let mut page = *Page::new(INVALID_PAGE_ID);
+        dm.read_page(id, &mut page)?;
+        Ok(page)
+    }
+
+    fn as_mock(dm: &dyn DiskManager) -> &DiskManagerMock {
         dm.as_any().downcast_ref::<DiskManagerMock>().unwrap()
     }
 
@@ -0,0 +1,390 @@
+use crate::buffer_pool::{FrameId, PageId, Replacer, ReplacerRep};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+// Adaptive Replacement Cache (Megiddo and Modha). Frames holding pages used once since they were
+// read in are in T1, those used again are in T2, both least recently used first. The page ids
+// evicted from each list are remembered in the ghost lists B1 and B2. Finding a page in B1 means
+// T1 was too small and grows the target size `p` of T1, finding it in B2 shrinks it, so the
+// cache keeps adapting between recency and frequency.
+//
+// Unlike in the paper the evicted frame is chosen before the page that needs it is known, so a
+// miss on a page in B2 doesn't favor evicting from T1 when it is exactly at its target size.
+pub struct ArcReplacer {
+    capacity: usize,
+    p: usize,
+    t1: VecDeque<FrameId>,
+    t2: VecDeque<FrameId>,
+    b1: VecDeque<PageId>,
+    b2: VecDeque<PageId>,
+    page_ids: HashMap<FrameId, PageId>,
+    evictable: BTreeSet<FrameId>,
+    // Victims, with the list and position they were taken from. Their pages only move to a ghost
+    // list once a new page is read into the frame.
+    evicted: HashMap<FrameId, (bool, usize)>,
+}
+
+fn remove<T: PartialEq>(list: &mut VecDeque<T>, value: &T) -> bool {
+    match list.iter().position(|item| item == value) {
+        Some(index) => list.remove(index).is_some(),
+        None => false,
+    }
+}
+
+impl ArcReplacer {
+    // `capacity` is the number of frames in the pool.
+    pub fn new(capacity: usize) -> ArcReplacer {
+        ArcReplacer {
+            capacity,
+            p: 0,
+            t1: VecDeque::new(),
+            t2: VecDeque::new(),
+            b1: VecDeque::new(),
+            b2: VecDeque::new(),
+            page_ids: HashMap::new(),
+            evictable: BTreeSet::new(),
+            evicted: HashMap::new(),
+        }
+    }
+
+    fn take_evictable(&mut self, from_t1: bool) -> Option<(FrameId, usize)> {
+        let evictable = &self.evictable;
+        let list = if from_t1 { &mut self.t1 } else { &mut self.t2 };
+        let index = list.iter().position(|id| evictable.contains(id))?;
+        Some((list.remove(index)?, index))
+    }
+
+    // The page of a victim was written back and is gone, so it is remembered in the ghost list
+    // of the list it was evicted from.
+    fn evict(&mut self, id: FrameId, from_t1: bool) {
+        if let Some(page_id) = self.page_ids.remove(&id) {
+            if from_t1 {
+                self.b1.push_back(page_id);
+            } else {
+                self.b2.push_back(page_id);
+            }
+            self.trim_ghosts();
+        }
+    }
+
+    // T1 and B1 together remember at most `capacity` pages, and all four lists twice that.
+    fn trim_ghosts(&mut self) {
+        while self.t1.len() + self.b1.len() > self.capacity && self.b1.pop_front().is_some() {}
+        while self.t1.len() + self.t2.len() + self.b1.len() + self.b2.len() > 2 * self.capacity
+            && self.b2.pop_front().is_some()
+        {}
+    }
+}
+
+impl Replacer for ArcReplacer {
+    // Evicts from T1 while it is larger than its target size and from T2 otherwise, falling back
+    // to the other list when every frame in the preferred one is pinned.
+    fn victim(&mut self) -> Option<FrameId> {
+        let from_t1 = self.t1.len() > self.p;
+        let (id, index, from_t1) = match self.take_evictable(from_t1) {
+            Some((id, index)) => (id, index, from_t1),
+            None => {
+                let (id, index) = self.take_evictable(!from_t1)?;
+                (id, index, !from_t1)
+            }
+        };
+        self.evictable.remove(&id);
+        self.evicted.insert(id, (from_t1, index));
+        Some(id)
+    }
+
+    // A frame unpinned before any access is treated as holding a page used once. A victim that
+    // is unpinned again still holds its page, because writing it back failed, so it goes back to
+    // where it was.
+    fn unpin(&mut self, id: FrameId) {
+        if let Some((from_t1, index)) = self.evicted.remove(&id) {
+            let list = if from_t1 { &mut self.t1 } else { &mut self.t2 };
+            list.insert(index.min(list.len()), id);
+        } else if !self.t1.contains(&id) && !self.t2.contains(&id) {
+            self.t1.push_back(id);
+        }
+        self.evictable.insert(id);
+    }
+
+    fn pin(&mut self, id: FrameId) {
+        self.evictable.remove(&id);
+    }
+
+    fn record_access(&mut self, id: FrameId, page_id: PageId) {
+        if let Some((from_t1, _)) = self.evicted.remove(&id) {
+            self.evict(id, from_t1);
+        }
+        if self.page_ids.get(&id) == Some(&page_id) {
+            if !remove(&mut self.t1, &id) {
+                remove(&mut self.t2, &id);
+            }
+            self.t2.push_back(id);
+            return;
+        }
+
+        // The frame holds a new page.
+        remove(&mut self.t1, &id);
+        remove(&mut self.t2, &id);
+        if self.b1.contains(&page_id) {
+            let delta = (self.b2.len() / self.b1.len()).max(1);
+            self.p = (self.p + delta).min(self.capacity);
+            remove(&mut self.b1, &page_id);
+            self.t2.push_back(id);
+        } else if self.b2.contains(&page_id) {
+            let delta = (self.b1.len() / self.b2.len()).max(1);
+            self.p = self.p.saturating_sub(delta);
+            remove(&mut self.b2, &page_id);
+            self.t2.push_back(id);
+        } else {
+            self.t1.push_back(id);
+        }
+        self.page_ids.insert(id, page_id);
+        self.trim_ghosts();
+    }
+
+    fn remove(&mut self, id: FrameId) {
+        remove(&mut self.t1, &id);
+        remove(&mut self.t2, &id);
+        self.page_ids.remove(&id);
+        self.evictable.remove(&id);
+        self.evicted.remove(&id);
+    }
+
+    fn forget_page(&mut self, page_id: PageId) {
+        remove(&mut self.b1, &page_id);
+        remove(&mut self.b2, &page_id);
+        // A victim whose page is deleted before a new page is read into the frame.
+        let evicted = &self.evicted;
+        self.page_ids
+            .retain(|id, held| *held != page_id || !evicted.contains_key(id));
+    }
+
+    fn remap(&mut self, moves: &[(PageId, PageId)]) {
+        let moves: HashMap<PageId, PageId> = moves.iter().copied().collect();
+        let page_ids = self.b1.iter_mut().chain(self.b2.iter_mut());
+        for page_id in page_ids.chain(self.page_ids.values_mut()) {
+            if let Some(new_id) = moves.get(page_id) {
+                *page_id = *new_id;
+            }
+        }
+    }
+
+    fn response(&self) -> ReplacerRep {
+        let rep = ArcReplacerRep {
+            capacity: self.capacity,
+            p: self.p,
+            t1: self.t1.iter().copied().collect(),
+            t2: self.t2.iter().copied().collect(),
+            b1: self.b1.iter().copied().collect(),
+            b2: self.b2.iter().copied().collect(),
+        };
+        ReplacerRep::new("ArcReplacer", &rep)
+    }
+}
+
+// T1 and T2 hold frames, B1 and B2 page ids, all least recently used first.
+#[derive(Deserialize, Serialize)]
+pub struct ArcReplacerRep {
+    #[serde(rename = "Capacity")]
+    pub capacity: usize,
+    #[serde(rename = "P")]
+    pub p: usize,
+    #[serde(rename = "T1")]
+    pub t1: Vec<FrameId>,
+    #[serde(rename = "T2")]
+    pub t2: Vec<FrameId>,
+    #[serde(rename = "B1")]
+    pub b1: Vec<PageId>,
+    #[serde(rename = "B2")]
+    pub b2: Vec<PageId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::{
+        ArcReplacer, ArcReplacerRep, BufferPoolManager, DiskManagerMock, FaultyDiskManager,
+        Operation, Replacer, MAX_POOL_SIZE,
+    };
+
+    fn rep(r: &ArcReplacer) -> ArcReplacerRep {
+        r.response().state("ArcReplacer").unwrap()
+    }
+
+    fn pool_rep(bpm: &BufferPoolManager) -> ArcReplacerRep {
+        bpm.response().replacer.state("ArcReplacer").unwrap()
+    }
+
+    #[test]
+    fn arc_replacer() {
+        let mut r = ArcReplacer::new(2);
+        r.record_access(0, 10);
+        r.record_access(1, 11);
+        r.record_access(0, 10);
+        r.unpin(0);
+        r.unpin(1);
+        assert_eq!(vec![1], rep(&r).t1);
+        assert_eq!(vec![0], rep(&r).t2);
+
+        // T1 is larger than its target size of 0.
+        assert_eq!(Some(1), r.victim());
+
+        // Page 11 was evicted too early, so T1 grows.
+        r.record_access(1, 11);
+        assert_eq!(1, rep(&r).p);
+        assert!(rep(&r).b1.is_empty());
+        assert_eq!(vec![0, 1], rep(&r).t2);
+        r.unpin(1);
+
+        assert_eq!(Some(0), r.victim());
+        r.record_access(0, 12);
+        assert_eq!(vec![10], rep(&r).b2);
+        assert_eq!(Some(1), r.victim());
+
+        // Page 10 was evicted from T2 too early, so T1 shrinks again.
+        r.record_access(1, 10);
+        assert_eq!(0, rep(&r).p);
+        assert_eq!(vec![0], rep(&r).t1);
+        assert_eq!(vec![1], rep(&r).t2);
+        assert_eq!(vec![11], rep(&r).b2);
+        assert_eq!(None, r.victim());
+
+        // Pinned frames are skipped.
+        r.unpin(0);
+        r.unpin(1);
+        r.pin(0);
+        assert_eq!(Some(1), r.victim());
+    }
+
+    #[test]
+    fn bounded_ghost_lists() {
+        let mut r = ArcReplacer::new(2);
+        for page_id in 0..10 {
+            r.record_access(0, page_id);
+            r.unpin(0);
+            assert_eq!(Some(0), r.victim());
+        }
+        r.record_access(0, 10);
+        // T1 and B1 never remember more than the capacity.
+        assert_eq!(vec![0], rep(&r).t1);
+        assert_eq!(vec![9], rep(&r).b1);
+    }
+
+    #[test]
+    fn deleted_page() {
+        let mut r = ArcReplacer::new(2);
+        r.record_access(0, 10);
+        r.record_access(0, 10);
+        r.unpin(0);
+        assert_eq!(vec![0], rep(&r).t2);
+
+        // Deleted while in a frame, so a new page with the same id in that frame is used once.
+        r.remove(0);
+        r.forget_page(10);
+        r.record_access(0, 10);
+        assert_eq!(vec![0], rep(&r).t1);
+        assert!(rep(&r).t2.is_empty());
+
+        // Deleted after being evicted, so coming back doesn't grow T1.
+        r.unpin(0);
+        assert_eq!(Some(0), r.victim());
+        r.forget_page(10);
+        assert!(rep(&r).b1.is_empty());
+        r.record_access(0, 10);
+        assert_eq!(0, rep(&r).p);
+        assert_eq!(vec![0], rep(&r).t1);
+    }
+
+    #[test]
+    fn remapped_pages() {
+        let mut r = ArcReplacer::new(2);
+        r.record_access(0, 10);
+        r.record_access(0, 10);
+        r.record_access(1, 11);
+        r.unpin(1);
+        assert_eq!(Some(1), r.victim());
+        r.record_access(1, 12);
+
+        r.remap(&[(10, 1), (11, 2)]);
+        assert_eq!(vec![2], rep(&r).b1);
+        // Still the same page, so it stays in T2.
+        r.record_access(0, 1);
+        assert_eq!(vec![0], rep(&r).t2);
+        assert_eq!(vec![1], rep(&r).t1);
+        r.record_access(1, 2);
+        assert_eq!(1, rep(&r).p);
+        assert_eq!(vec![0, 1], rep(&r).t2);
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let replacer = Box::new(ArcReplacer::new(MAX_POOL_SIZE));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(id, false).unwrap();
+        }
+        for id in 1..=2 {
+            bpm.fetch_page(id).unwrap();
+            bpm.unpin_page(id, false).unwrap();
+        }
+
+        // A scan through new pages only replaces pages used once.
+        for _ in 0..4 {
+            let id = bpm.new_page().unwrap().id();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        assert!(bpm.page_table.contains_key(&1));
+        assert!(bpm.page_table.contains_key(&2));
+        let rep = pool_rep(&bpm);
+        assert!(rep.p == 0 && rep.t2 == vec![0, 1] && rep.b1 == vec![5, 6]);
+    }
+
+    #[test]
+    fn failed_eviction() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Write).on_page(1).times(1);
+        let replacer = Box::new(ArcReplacer::new(MAX_POOL_SIZE));
+        let mut bpm = BufferPoolManager::with_replacer(dm, replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(id, false).unwrap();
+        }
+
+        // Page 1 can't be written back, so it stays where it was and isn't a ghost.
+        assert!(bpm.new_page().is_err());
+        let rep = pool_rep(&bpm);
+        assert!(rep.t1 == vec![0, 1, 2, 3] && rep.b1.is_empty());
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        let rep = pool_rep(&bpm);
+        assert!(rep.p == 0 && rep.t1 == vec![1, 2, 3] && rep.t2 == vec![0]);
+    }
+
+    #[test]
+    fn reuse_deleted_page_id() {
+        let replacer = Box::new(ArcReplacer::new(MAX_POOL_SIZE));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(id, false).unwrap();
+        }
+        for id in 3..=4 {
+            bpm.fetch_page(id).unwrap();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        assert_eq!(5, bpm.new_page().unwrap().id());
+        bpm.unpin_page(5, false).unwrap();
+
+        // Page 1 was evicted and is deleted, so the next page to get its id is used once.
+        bpm.delete_page(1).unwrap();
+        assert_eq!(1, bpm.new_page().unwrap().id());
+        let rep = pool_rep(&bpm);
+        assert!(rep.p == 0 && rep.t2 == vec![2, 3] && rep.b1 == vec![2]);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::buffer_pool::{FrameId, Replacer};
+use crate::buffer_pool::{FrameId, Replacer, ReplacerRep};
 use serde::{Deserialize, Serialize};
 
+#[derive(Default)]
 pub struct ClockReplacer {
     list: Vec<(FrameId, bool)>,
     current: usize,
@@ -52,6 +53,21 @@ impl Replacer for ClockReplacer {
             self.remove(index);
         }
     }
+
+    fn response(&self) -> ReplacerRep {
+        let mut clock: Vec<ClockValue> = Vec::new();
+        for (id, value) in self.list.iter() {
+            clock.push(ClockValue {
+                clock_frame: *id,
+                reference_value: *value,
+            });
+        }
+        let rep = ClockReplacerRep {
+            clock_hand: self.current as i32,
+            clock,
+        };
+        ReplacerRep::new("ClockReplacer", &rep)
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -70,22 +86,6 @@ pub struct ClockValue {
     reference_value: bool,
 }
 
-impl ClockReplacer {
-    pub fn response(&self) -> ClockReplacerRep {
-        let mut clock: Vec<ClockValue> = Vec::new();
-        for (id, value) in self.list.iter() {
-            clock.push(ClockValue {
-                clock_frame: *id,
-                reference_value: *value,
-            });
-        }
-        return ClockReplacerRep {
-            clock_hand: self.current as i32,
-            clock,
-        };
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use crate::buffer_pool::{ClockReplacer, Replacer};
@@ -3,20 +3,25 @@ use crate::buffer_pool::PageError::{OutOfStorage, PageNotFound};
 use std::any::Any;
 
 impl DiskManager for DiskManagerMock {
-    fn read_page(&mut self, id: PageId) -> Result<&Box<Page>, PageError> {
-        if let Some(page) = self.pages.get_mut(&id) {
-            Ok(page)
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        if let Some(data) = self.pages.get(&id) {
+            page.id = id;
+            page.data = *data;
+            Ok(())
         } else {
             Err(PageNotFound)
         }
     }
 
-    fn write_page(&mut self, page: &Box<Page>) -> Result<(), PageError> {
-        self.pages.insert(page.id(), page.clone());
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        self.pages.insert(page.id(), page.data);
         Ok(())
     }
 
     fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        if let Some(id) = self.free_pages.pop_first() {
+            return Ok(id);
+        }
         if self.num_pages >= MAX_NUM_DISK_PAGES {
             return Err(OutOfStorage);
         }
@@ -24,8 +29,31 @@ impl DiskManager for DiskManagerMock {
         Ok(self.num_pages)
     }
 
-    fn deallocate_page(&mut self, id: PageId) {
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if id < 1 || id > self.num_pages || !self.free_pages.insert(id) {
+            return Err(PageNotFound);
+        }
         self.pages.remove(&id);
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        let live: Vec<PageId> = (1..=self.num_pages)
+            .filter(|id| !self.free_pages.contains(id))
+            .collect();
+        let mut moves = Vec::new();
+        for (index, old_id) in live.iter().enumerate() {
+            let new_id = index as PageId + 1;
+            if *old_id != new_id {
+                if let Some(data) = self.pages.remove(old_id) {
+                    self.pages.insert(new_id, data);
+                }
+                moves.push((*old_id, new_id));
+            }
+        }
+        self.num_pages = live.len() as i32;
+        self.free_pages.clear();
+        Ok(moves)
     }
 
     fn pages_on_disk(&self) -> Vec<i32> {
@@ -0,0 +1,325 @@
+use crate::buffer_pool::file_disk_manager::HEADER_PAGE_ID;
+use crate::buffer_pool::PageError::{Corrupted, PageNotFound};
+use crate::buffer_pool::{DiskManager, FileDiskManager, Page, PageError, PageId, PAGE_SIZE};
+use std::any::Any;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const RECORD_SIZE: usize = 4 + PAGE_SIZE + 4;
+
+// Protects a persistent disk manager against torn page writes. Pages are first written and
+// synced to a sequential doublewrite file, and only then to their home location. When opened,
+// any home page that fails its checksum is repaired from its doublewrite copy.
+//
+// The header page of the inner disk manager is saved the same way before every allocation,
+// deallocation and compaction changes it. Only `open_file` can repair it though, since a torn header page keeps
+// the inner disk manager from being opened.
+pub struct DoubleWriteDiskManager<D: DiskManager> {
+    inner: D,
+    file: File,
+}
+
+impl<D: DiskManager> DoubleWriteDiskManager<D> {
+    pub fn open<P: AsRef<Path>>(
+        inner: D,
+        path: P,
+    ) -> Result<Box<DoubleWriteDiskManager<D>>, PageError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut manager = Box::new(DoubleWriteDiskManager { inner, file });
+        manager.recover()?;
+        Ok(manager)
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    // Replaces the contents of the doublewrite file with `pages` and makes them durable.
+    fn write_records(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        let mut records = Vec::with_capacity(pages.len() * RECORD_SIZE);
+        for page in pages {
+            DoubleWriteDiskManager::<D>::encode(page, &mut records);
+        }
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&records)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn save_header_page(&mut self) -> Result<(), PageError> {
+        match self.inner.header_page() {
+            Some(header) => self.write_records(&[&header]),
+            None => Ok(()),
+        }
+    }
+
+    fn recover(&mut self) -> Result<(), PageError> {
+        let mut records = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut records)?;
+        for record in records.chunks_exact(RECORD_SIZE) {
+            // A torn doublewrite record means the crash happened before the home write started.
+            let page = match DoubleWriteDiskManager::<D>::decode(record) {
+                Some(page) => page,
+                None => continue,
+            };
+            let mut home = page;
+            match self.inner.read_page(page.id, &mut home) {
+                Err(Corrupted { .. }) => self.inner.write_page(&page)?,
+                Ok(_) | Err(PageNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.inner.sync()?;
+        self.file.set_len(0)?;
+        Ok(())
+    }
+
+    fn encode(page: &Page, record: &mut Vec<u8>) {
+        record.extend_from_slice(&page.id.to_le_bytes());
+        record.extend_from_slice(&page.data);
+        record.extend_from_slice(&page.checksum().to_le_bytes());
+    }
+
+    fn decode(record: &[u8]) -> Option<Page> {
+        let mut id = [0; 4];
+        id.copy_from_slice(&record[..4]);
+        let mut page = *Page::new(PageId::from_le_bytes(id));
+        page.data.copy_from_slice(&record[4..4 + PAGE_SIZE]);
+        let mut checksum = [0; 4];
+        checksum.copy_from_slice(&record[4 + PAGE_SIZE..]);
+        if u32::from_le_bytes(checksum) == page.checksum() {
+            Some(page)
+        } else {
+            None
+        }
+    }
+}
+
+impl DoubleWriteDiskManager<Box<FileDiskManager>> {
+    // Opens a data file behind a doublewrite file, first putting back a torn header page from its
+    // saved copy.
+    pub fn open_file<P: AsRef<Path>, Q: AsRef<Path>>(
+        data_path: P,
+        path: Q,
+    ) -> Result<Box<DoubleWriteDiskManager<Box<FileDiskManager>>>, PageError> {
+        let records = match fs::read(path.as_ref()) {
+            Ok(records) => records,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        if data_path.as_ref().exists() {
+            for record in records.chunks_exact(RECORD_SIZE) {
+                if let Some(page) = DoubleWriteDiskManager::<Box<FileDiskManager>>::decode(record) {
+                    if page.id == HEADER_PAGE_ID {
+                        FileDiskManager::repair_header_page(data_path.as_ref(), &page)?;
+                    }
+                }
+            }
+        }
+        DoubleWriteDiskManager::open(FileDiskManager::open(data_path)?, path)
+    }
+}
+
+impl<D: DiskManager + 'static> DiskManager for DoubleWriteDiskManager<D> {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        self.inner.read_page(id, page)
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        self.write_pages(&[page])
+    }
+
+    // Every call syncs twice, once for the doublewrite file and once for the home pages, so
+    // writing many pages in one call is much cheaper than writing them one at a time.
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        self.write_records(pages)?;
+        self.inner.write_pages(pages)?;
+        self.inner.sync()?;
+        self.file.set_len(0)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.inner.sync()
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        self.save_header_page()?;
+        let id = self.inner.allocate_page()?;
+        self.inner.sync()?;
+        self.file.set_len(0)?;
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        self.save_header_page()?;
+        self.inner.deallocate_page(id)?;
+        self.inner.sync()?;
+        self.file.set_len(0)?;
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        self.save_header_page()?;
+        let moves = self.inner.compact()?;
+        self.inner.sync()?;
+        self.file.set_len(0)?;
+        Ok(moves)
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.inner.pages_on_disk()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        self.inner.header_page()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::{Corrupted, IoError};
+    use crate::buffer_pool::tests::{corrupt_page, data_file, page, read};
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, DoubleWriteDiskManager, FaultyDiskManager, FileDiskManager,
+        Operation,
+    };
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+    #[test]
+    fn repair_torn_page() {
+        let path = data_file("double_write.db");
+        let dw_path = data_file("double_write.dw");
+        {
+            let mut inner = FaultyDiskManager::new(FileDiskManager::open(&path).unwrap());
+            inner.tear_writes(4).after(2).times(1);
+            inner.fail(Operation::Write).after(3);
+            let mut dm = DoubleWriteDiskManager::open(inner, &dw_path).unwrap();
+            for _ in 0..2 {
+                dm.allocate_page().unwrap();
+            }
+            dm.write_pages(&[&page(1, b"11111111"), &page(2, b"22222222")])
+                .unwrap();
+
+            // The crash happens while the home pages are being written, half way through page 1.
+            assert_eq!(
+                IoError(ErrorKind::Other),
+                dm.write_pages(&[&page(1, b"aaaaaaaa"), &page(2, b"bbbbbbbb")])
+                    .unwrap_err()
+            );
+        }
+        assert_eq!(
+            Corrupted { page_id: 1 },
+            read(&mut FileDiskManager::open(&path).unwrap(), 1).unwrap_err()
+        );
+
+        let mut dm =
+            DoubleWriteDiskManager::open(FileDiskManager::open(&path).unwrap(), &dw_path).unwrap();
+        assert_eq!(*b"aaaaaaaa", read(&mut dm, 1).unwrap().data);
+        // Page 2 never got its new contents, but isn't torn either.
+        assert_eq!(*b"22222222", read(&mut dm, 2).unwrap().data);
+        assert_eq!(0, fs::metadata(&dw_path).unwrap().len());
+    }
+
+    #[test]
+    fn ignore_torn_doublewrite_record() {
+        let path = data_file("double_write_torn_record.db");
+        let dw_path = data_file("double_write_torn_record.dw");
+        {
+            let mut inner = FaultyDiskManager::new(FileDiskManager::open(&path).unwrap());
+            inner.fail(Operation::Write).after(1);
+            let mut dm = DoubleWriteDiskManager::open(inner, &dw_path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_page(&page(1, b"11111111")).unwrap();
+            assert!(dm.write_page(&page(1, b"aaaaaaaa")).is_err());
+        }
+        let mut file = OpenOptions::new().write(true).open(&dw_path).unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(&[0xff]).unwrap();
+
+        let mut dm =
+            DoubleWriteDiskManager::open(FileDiskManager::open(&path).unwrap(), &dw_path).unwrap();
+        assert_eq!(*b"11111111", read(&mut dm, 1).unwrap().data);
+    }
+
+    #[test]
+    fn repair_torn_header_page() {
+        let path = data_file("double_write_header.db");
+        let dw_path = data_file("double_write_header.dw");
+        {
+            let mut inner = FaultyDiskManager::new(FileDiskManager::open(&path).unwrap());
+            inner.fail(Operation::Allocate).after(1);
+            let mut dm = DoubleWriteDiskManager::open(inner, &dw_path).unwrap();
+            dm.allocate_page().unwrap();
+            assert!(dm.allocate_page().is_err());
+        }
+        // The crash happened while the allocation map was being written.
+        corrupt_page(&path, 0);
+        assert_eq!(
+            Some(Corrupted { page_id: 0 }),
+            FileDiskManager::open(&path).err()
+        );
+
+        let mut dm = DoubleWriteDiskManager::open_file(&path, &dw_path).unwrap();
+        assert_eq!(vec![1], dm.pages_on_disk());
+        assert_eq!(2, dm.allocate_page().unwrap());
+        assert_eq!(0, fs::metadata(&dw_path).unwrap().len());
+    }
+
+    #[test]
+    fn repair_torn_compact() {
+        let path = data_file("double_write_compact.db");
+        let dw_path = data_file("double_write_compact.dw");
+        {
+            let mut inner = FaultyDiskManager::new(FileDiskManager::open(&path).unwrap());
+            inner.fail(Operation::Compact);
+            let mut dm = DoubleWriteDiskManager::open(inner, &dw_path).unwrap();
+            for data in [b"11111111", b"22222222", b"33333333"] {
+                let id = dm.allocate_page().unwrap();
+                dm.write_page(&page(id, data)).unwrap();
+            }
+            dm.deallocate_page(1).unwrap();
+            assert!(dm.compact().is_err());
+        }
+        // The crash happened while the compacted allocation map was being written.
+        corrupt_page(&path, 0);
+
+        let mut dm = DoubleWriteDiskManager::open_file(&path, &dw_path).unwrap();
+        assert_eq!(vec![2, 3], dm.pages_on_disk());
+        assert_eq!(*b"33333333", read(&mut dm, 3).unwrap().data);
+        assert_eq!(vec![(3, 1)], dm.compact().unwrap());
+        assert_eq!(*b"33333333", read(&mut dm, 1).unwrap().data);
+        assert_eq!(0, fs::metadata(&dw_path).unwrap().len());
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let path = data_file("double_write_bpm.db");
+        let dw_path = data_file("double_write_bpm.dw");
+        {
+            let dm = DoubleWriteDiskManager::open(FileDiskManager::open(&path).unwrap(), &dw_path)
+                .unwrap();
+            let mut bpm = BufferPoolManager::new(dm);
+            bpm.new_page().unwrap().data = *b"pagedata";
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(*b"pagedata", read(&mut dm, 1).unwrap().data);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::buffer_pool::PageError::IoError;
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId};
+use std::any::Any;
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operation {
+    Read,
+    Write,
+    Allocate,
+    Deallocate,
+    Compact,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Fault {
+    Error,
+    TornWrite(usize),
+    DropWrite,
+    Latency(Duration),
+}
+
+pub struct FaultRule {
+    operation: Operation,
+    fault: Fault,
+    page_id: Option<PageId>,
+    after: usize,
+    times: Option<usize>,
+    seen: usize,
+    fired: usize,
+}
+
+impl FaultRule {
+    // Only applies to operations on the given page. Never matches allocations.
+    pub fn on_page(&mut self, id: PageId) -> &mut FaultRule {
+        self.page_id = Some(id);
+        self
+    }
+
+    // Lets the first `n` matching operations through.
+    pub fn after(&mut self, n: usize) -> &mut FaultRule {
+        self.after = n;
+        self
+    }
+
+    // Stops injecting after firing `n` times.
+    pub fn times(&mut self, n: usize) -> &mut FaultRule {
+        self.times = Some(n);
+        self
+    }
+
+    fn fires(&mut self, operation: Operation, page_id: Option<PageId>) -> bool {
+        if self.operation != operation || (self.page_id.is_some() && self.page_id != page_id) {
+            return false;
+        }
+        self.seen += 1;
+        if self.seen <= self.after || self.times.is_some_and(|times| self.fired >= times) {
+            return false;
+        }
+        self.fired += 1;
+        true
+    }
+}
+
+// Wraps another disk manager and injects scripted faults into its operations.
+pub struct FaultyDiskManager<D: DiskManager> {
+    inner: D,
+    rules: Vec<FaultRule>,
+}
+
+impl<D: DiskManager> FaultyDiskManager<D> {
+    pub fn new(inner: D) -> Box<FaultyDiskManager<D>> {
+        Box::new(FaultyDiskManager {
+            inner,
+            rules: Vec::new(),
+        })
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    // Fails the operation with an I/O error without touching the wrapped disk manager.
+    pub fn fail(&mut self, operation: Operation) -> &mut FaultRule {
+        self.add_rule(operation, Fault::Error)
+    }
+
+    // Only the first `bytes` of the page, as the wrapped disk manager stores it, are written and
+    // the rest keeps its old contents. The write still reports success, like a crash right after
+    // a torn write would.
+    pub fn tear_writes(&mut self, bytes: usize) -> &mut FaultRule {
+        self.add_rule(Operation::Write, Fault::TornWrite(bytes))
+    }
+
+    // Reports success without writing anything.
+    pub fn drop_writes(&mut self) -> &mut FaultRule {
+        self.add_rule(Operation::Write, Fault::DropWrite)
+    }
+
+    pub fn delay(&mut self, operation: Operation, latency: Duration) -> &mut FaultRule {
+        self.add_rule(operation, Fault::Latency(latency))
+    }
+
+    fn add_rule(&mut self, operation: Operation, fault: Fault) -> &mut FaultRule {
+        self.rules.push(FaultRule {
+            operation,
+            fault,
+            page_id: None,
+            after: 0,
+            times: None,
+            seen: 0,
+            fired: 0,
+        });
+        self.rules.last_mut().unwrap()
+    }
+
+    // Sleeps for any matching latency rules and returns the first other fault that fires.
+    fn inject(&mut self, operation: Operation, page_id: Option<PageId>) -> Option<Fault> {
+        let mut result = None;
+        for rule in self.rules.iter_mut() {
+            if rule.fires(operation, page_id) {
+                match rule.fault {
+                    Fault::Latency(latency) => thread::sleep(latency),
+                    fault => {
+                        result = result.or(Some(fault));
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<D: DiskManager + 'static> DiskManager for FaultyDiskManager<D> {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        if let Some(Fault::Error) = self.inject(Operation::Read, Some(id)) {
+            return Err(IoError(ErrorKind::Other));
+        }
+        self.inner.read_page(id, page)
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        match self.inject(Operation::Write, Some(page.id)) {
+            Some(Fault::Error) => Err(IoError(ErrorKind::Other)),
+            Some(Fault::DropWrite) => Ok(()),
+            Some(Fault::TornWrite(bytes)) => self.inner.write_page_torn(page, bytes),
+            _ => self.inner.write_page(page),
+        }
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.inner.sync()
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        if let Some(Fault::Error) = self.inject(Operation::Allocate, None) {
+            return Err(IoError(ErrorKind::Other));
+        }
+        self.inner.allocate_page()
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if let Some(Fault::Error) = self.inject(Operation::Deallocate, Some(id)) {
+            return Err(IoError(ErrorKind::Other));
+        }
+        self.inner.deallocate_page(id)
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        if let Some(Fault::Error) = self.inject(Operation::Compact, None) {
+            return Err(IoError(ErrorKind::Other));
+        }
+        self.inner.compact()
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.inner.pages_on_disk()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        self.inner.header_page()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::IoError;
+    use crate::buffer_pool::tests::{page, read};
+    use crate::buffer_pool::{DiskManager, DiskManagerMock, FaultyDiskManager, Operation, Page};
+    use std::io::ErrorKind;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn fail_on_page_after() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Read).on_page(2).after(1).times(1);
+        for _ in 0..2 {
+            let id = dm.allocate_page().unwrap();
+            dm.write_page(&Page::new(id)).unwrap();
+        }
+
+        read(&mut dm, 1).unwrap();
+        read(&mut dm, 2).unwrap();
+        assert_eq!(IoError(ErrorKind::Other), read(&mut dm, 2).unwrap_err());
+        read(&mut dm, 2).unwrap();
+    }
+
+    #[test]
+    fn torn_and_dropped_writes() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.tear_writes(3).after(1).times(1);
+        dm.drop_writes().after(2);
+        let id = dm.allocate_page().unwrap();
+
+        dm.write_page(&page(id, b"aaaaaaaa")).unwrap();
+        dm.write_page(&page(id, b"bbbbbbbb")).unwrap();
+        assert_eq!(*b"bbbaaaaa", read(&mut dm, id).unwrap().data);
+
+        dm.write_page(&page(id, b"cccccccc")).unwrap();
+        assert_eq!(*b"bbbaaaaa", read(&mut dm, id).unwrap().data);
+    }
+
+    #[test]
+    fn latency() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.delay(Operation::Allocate, Duration::from_millis(20))
+            .times(1);
+
+        let start = Instant::now();
+        dm.allocate_page().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
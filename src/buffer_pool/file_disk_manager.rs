@@ -0,0 +1,1401 @@
+use crate::buffer_pool::file_io::{AlignedBuffer, BufferedIo, FileIo, BLOCK_SIZE};
+use crate::buffer_pool::page::checksum;
+use crate::buffer_pool::PageError::{
+    Corrupted, OutOfStorage, PageNotFound, PageSizeMismatch, UnsupportedFeatures,
+    UnsupportedVersion,
+};
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, MAX_NUM_DISK_PAGES, PAGE_SIZE};
+use std::any::Any;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io::IoSlice;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) const HEADER_PAGE_ID: PageId = 0;
+const CHECKSUM_SIZE: usize = 4;
+pub(crate) const SLOT_SIZE: usize = PAGE_SIZE + CHECKSUM_SIZE;
+
+const MAGIC: [u8; 8] = *b"BPMDATA\0";
+const FILE_HEADER_SIZE: usize = 64;
+// Version 1 files have no file header, they start right at the header page.
+const LEGACY_VERSION: u32 = 1;
+pub const FORMAT_VERSION: u32 = 2;
+pub const FLAG_PAGE_CHECKSUMS: u64 = 1;
+// The file header and every slot take up a block of their own, so that pages can be written with
+// O_DIRECT without reading the blocks around them first.
+pub const FLAG_BLOCK_SLOTS: u64 = 2;
+const SUPPORTED_FLAGS: u64 = FLAG_PAGE_CHECKSUMS | FLAG_BLOCK_SLOTS;
+
+// The allocation bitmap lives in the header page, one bit per page id.
+const _: () = assert!((MAX_NUM_DISK_PAGES as usize) < PAGE_SIZE * 8);
+
+pub(crate) fn slot_offset(id: PageId) -> u64 {
+    FILE_HEADER_SIZE as u64 + id as u64 * SLOT_SIZE as u64
+}
+
+// Describes the format of a data file. Stored little endian in the first `FILE_HEADER_SIZE`
+// bytes of the file: magic, version, page size, creation time in seconds since the epoch and
+// feature flags, with a CRC32C of everything before it in the last 4 bytes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FileHeader {
+    pub version: u32,
+    pub page_size: usize,
+    pub created: SystemTime,
+    pub flags: u64,
+}
+
+impl FileHeader {
+    fn new() -> FileHeader {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        FileHeader {
+            version: FORMAT_VERSION,
+            page_size: PAGE_SIZE,
+            created: UNIX_EPOCH + Duration::from_secs(now.as_secs()),
+            flags: FLAG_PAGE_CHECKSUMS,
+        }
+    }
+
+    fn encode(&self) -> [u8; FILE_HEADER_SIZE] {
+        let created = self
+            .created
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut bytes = [0; FILE_HEADER_SIZE];
+        bytes[..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+        bytes[16..24].copy_from_slice(&created.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.flags.to_le_bytes());
+        let crc = crc32c::crc32c(&bytes[..FILE_HEADER_SIZE - CHECKSUM_SIZE]);
+        bytes[FILE_HEADER_SIZE - CHECKSUM_SIZE..].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    // Returns `None` when the file doesn't start with the magic number.
+    fn decode(bytes: &[u8]) -> Result<Option<FileHeader>, PageError> {
+        if bytes.len() < FILE_HEADER_SIZE || bytes[..8] != MAGIC {
+            return Ok(None);
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let mut field = [0; 8];
+            field[..range.len()].copy_from_slice(&bytes[range]);
+            u64::from_le_bytes(field)
+        };
+        let crc = field(FILE_HEADER_SIZE - CHECKSUM_SIZE..FILE_HEADER_SIZE) as u32;
+        if crc != crc32c::crc32c(&bytes[..FILE_HEADER_SIZE - CHECKSUM_SIZE]) {
+            return Err(Corrupted {
+                page_id: HEADER_PAGE_ID,
+            });
+        }
+        Ok(Some(FileHeader {
+            version: field(8..12) as u32,
+            page_size: field(12..16) as usize,
+            created: UNIX_EPOCH + Duration::from_secs(field(16..24)),
+            flags: field(24..32),
+        }))
+    }
+
+    // Where the slot of a page starts in the file, with the header page as page 0.
+    fn slot_offset(&self, id: PageId) -> u64 {
+        if self.flags & FLAG_BLOCK_SLOTS != 0 {
+            (id as u64 + 1) * BLOCK_SIZE as u64
+        } else {
+            slot_offset(id)
+        }
+    }
+
+    fn slot_size(&self) -> usize {
+        if self.flags & FLAG_BLOCK_SLOTS != 0 {
+            BLOCK_SIZE
+        } else {
+            SLOT_SIZE
+        }
+    }
+
+    // The version decides the layout of the rest of the header, so it is checked first.
+    fn validate(&self) -> Result<(), PageError> {
+        if self.version != FORMAT_VERSION {
+            return Err(UnsupportedVersion {
+                found: self.version,
+            });
+        }
+        if self.flags & !SUPPORTED_FLAGS != 0 {
+            return Err(UnsupportedFeatures { flags: self.flags });
+        }
+        if self.page_size != PAGE_SIZE {
+            return Err(PageSizeMismatch {
+                expected: PAGE_SIZE,
+                found: self.page_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Version 1 files are recognized by a valid header page at the very start of the file.
+fn is_legacy(bytes: &[u8]) -> bool {
+    if bytes.len() < SLOT_SIZE {
+        return false;
+    }
+    let mut crc = [0; CHECKSUM_SIZE];
+    crc.copy_from_slice(&bytes[PAGE_SIZE..SLOT_SIZE]);
+    u32::from_le_bytes(crc) == checksum(HEADER_PAGE_ID, &bytes[..PAGE_SIZE])
+}
+
+// Verifies a page of `page_size` bytes from the slot at `start`.
+pub(crate) fn decode_slot(
+    bytes: &[u8],
+    start: usize,
+    page_size: usize,
+    id: PageId,
+) -> Result<Page, PageError> {
+    let slot = bytes
+        .get(start..start + page_size + CHECKSUM_SIZE)
+        .ok_or(Corrupted { page_id: id })?;
+    let mut crc = [0; CHECKSUM_SIZE];
+    crc.copy_from_slice(&slot[page_size..]);
+    if u32::from_le_bytes(crc) != checksum(id, &slot[..page_size]) {
+        return Err(Corrupted { page_id: id });
+    }
+    let mut page = *Page::new(id);
+    page.data[..page_size].copy_from_slice(&slot[..page_size]);
+    Ok(page)
+}
+
+fn is_set(bitmap: &[u8], id: usize) -> bool {
+    bitmap[id / 8] & (1 << (id % 8)) != 0
+}
+
+// The outcome of `FileDiskManager::check`. Problems are listed by page id, with the header page
+// as page 0.
+#[derive(Debug, PartialEq)]
+pub struct CheckReport {
+    pub file_header: FileHeader,
+    pub allocated: Vec<PageId>,
+    pub free: Vec<PageId>,
+    pub problems: Vec<(PageId, PageError)>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// A page slot as stored in the file, whether or not its checksum matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotDump {
+    pub data: Vec<u8>,
+    pub stored_checksum: u32,
+    pub checksum: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpaceUsage {
+    // The size of the file.
+    pub logical_bytes: u64,
+    // What the file takes up on disk, less than its size once whole blocks of free slots have
+    // been punched.
+    pub allocated_bytes: u64,
+    // The file header, header page and allocated pages.
+    pub live_bytes: u64,
+}
+
+// Pages are stored at `page_id * SLOT_SIZE` after the file header, each followed by its CRC32C
+// checksum. Page ids start at 1 so the first slot of the file holds the header page. With
+// `FLAG_BLOCK_SLOTS` each slot is padded to a block instead.
+pub struct FileDiskManager {
+    io: Box<dyn FileIo>,
+    file_header: FileHeader,
+    header: Box<Page>,
+    // Slots are encoded here, block aligned for O_DIRECT.
+    slot: AlignedBuffer,
+}
+
+impl FileDiskManager {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<FileDiskManager>, PageError> {
+        FileDiskManager::open_with(Box::new(BufferedIo::open(path.as_ref())?))
+    }
+
+    // Bypasses the kernel page cache with O_DIRECT. New files are created with
+    // `FLAG_BLOCK_SLOTS`, so every page is read and written as one block. Files with packed slots,
+    // and filesystems that don't support O_DIRECT, fall back to buffered I/O.
+    pub fn open_direct<P: AsRef<Path>>(path: P) -> Result<Box<FileDiskManager>, PageError> {
+        #[cfg(target_os = "linux")]
+        {
+            use crate::buffer_pool::file_io::DirectIo;
+            match DirectIo::open(path.as_ref()) {
+                Ok(io) => {
+                    let manager = FileDiskManager::open_with_flags(
+                        Box::new(io),
+                        FLAG_PAGE_CHECKSUMS | FLAG_BLOCK_SLOTS,
+                    )?;
+                    if manager.file_header.flags & FLAG_BLOCK_SLOTS != 0 {
+                        return Ok(manager);
+                    }
+                }
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        FileDiskManager::open(path)
+    }
+
+    // Performs page I/O through an io_uring submission and completion queue.
+    #[cfg(target_os = "linux")]
+    pub fn open_uring<P: AsRef<Path>>(path: P) -> Result<Box<FileDiskManager>, PageError> {
+        use crate::buffer_pool::file_io::UringIo;
+        FileDiskManager::open_with(Box::new(UringIo::open(path.as_ref())?))
+    }
+
+    // Serves pages out of a shared memory mapping of the data file. With `sync_writes` every
+    // page write is followed by an msync.
+    #[cfg(target_os = "linux")]
+    pub fn open_mmap<P: AsRef<Path>>(
+        path: P,
+        sync_writes: bool,
+    ) -> Result<Box<FileDiskManager>, PageError> {
+        use crate::buffer_pool::file_io::MmapIo;
+        FileDiskManager::open_with(Box::new(MmapIo::open(path.as_ref(), sync_writes)?))
+    }
+
+    // Rewrites a file written by an older format version, or with a smaller `PAGE_SIZE`, in the
+    // current format, padding pages with zeros. Returns whether anything had to be rewritten.
+    //
+    // The new file is written next to the old one and renamed over it, so a crash leaves either
+    // the old or the upgraded file behind.
+    pub fn upgrade<P: AsRef<Path>>(path: P) -> Result<bool, PageError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        // Old slots are found at `first + id * stride`.
+        let (mut file_header, first, stride, page_size) = match FileHeader::decode(&bytes)? {
+            Some(file_header) if file_header.version != FORMAT_VERSION => {
+                return Err(UnsupportedVersion {
+                    found: file_header.version,
+                })
+            }
+            Some(file_header) if file_header.page_size > PAGE_SIZE => {
+                return Err(PageSizeMismatch {
+                    expected: PAGE_SIZE,
+                    found: file_header.page_size,
+                })
+            }
+            Some(file_header) if file_header.page_size == PAGE_SIZE => {
+                file_header.validate()?;
+                return Ok(false);
+            }
+            Some(file_header) if file_header.flags & FLAG_BLOCK_SLOTS != 0 => {
+                (file_header, BLOCK_SIZE, BLOCK_SIZE, file_header.page_size)
+            }
+            Some(file_header) => (
+                file_header,
+                FILE_HEADER_SIZE,
+                file_header.page_size + CHECKSUM_SIZE,
+                file_header.page_size,
+            ),
+            None if is_legacy(&bytes) => (FileHeader::new(), 0, SLOT_SIZE, PAGE_SIZE),
+            None => {
+                return Err(Corrupted {
+                    page_id: HEADER_PAGE_ID,
+                })
+            }
+        };
+        file_header.page_size = PAGE_SIZE;
+        file_header.validate()?;
+
+        // Only allocated pages are carried over, each verified against its old checksum first.
+        let old_page =
+            |id: PageId| decode_slot(&bytes, first + id as usize * stride, page_size, id);
+        let header = old_page(HEADER_PAGE_ID)?;
+        let mut upgraded = vec![0; file_header.slot_offset(MAX_NUM_DISK_PAGES + 1) as usize];
+        upgraded[..FILE_HEADER_SIZE].copy_from_slice(&file_header.encode());
+        for id in HEADER_PAGE_ID..=MAX_NUM_DISK_PAGES {
+            let page = if id == HEADER_PAGE_ID {
+                header
+            } else if is_set(&header.data, id as usize) {
+                old_page(id)?
+            } else {
+                *Page::new(id)
+            };
+            let start = file_header.slot_offset(id) as usize;
+            encode_slot(&page, &mut upgraded[start..start + file_header.slot_size()]);
+        }
+
+        let mut upgrade_path: OsString = path.as_os_str().to_owned();
+        upgrade_path.push(".upgrade");
+        let upgrade_path = PathBuf::from(upgrade_path);
+        fs::write(&upgrade_path, &upgraded)?;
+        File::open(&upgrade_path)?.sync_all()?;
+        fs::rename(&upgrade_path, path)?;
+        Ok(true)
+    }
+
+    fn open_with(io: Box<dyn FileIo>) -> Result<Box<FileDiskManager>, PageError> {
+        FileDiskManager::open_with_flags(io, FLAG_PAGE_CHECKSUMS)
+    }
+
+    // `flags` only apply when the file is created, an existing file keeps its own.
+    fn open_with_flags(io: Box<dyn FileIo>, flags: u64) -> Result<Box<FileDiskManager>, PageError> {
+        let len = io.len()?;
+        let mut manager = Box::new(FileDiskManager {
+            io,
+            file_header: FileHeader::new(),
+            header: Page::new(HEADER_PAGE_ID),
+            slot: AlignedBuffer::new(BLOCK_SIZE),
+        });
+        if len == 0 {
+            manager.file_header.flags = flags;
+            let end = manager.file_header.slot_offset(HEADER_PAGE_ID) as usize;
+            manager.slot.fill(0);
+            manager.slot[..FILE_HEADER_SIZE].copy_from_slice(&manager.file_header.encode());
+            manager.io.write_at(&manager.slot[..end], 0)?;
+            manager.write_header()?;
+        } else {
+            let mut bytes = vec![0; (len as usize).min(FILE_HEADER_SIZE)];
+            manager.io.read_at(&mut bytes, 0)?;
+            manager.file_header = match FileHeader::decode(&bytes)? {
+                Some(file_header) => file_header,
+                None if is_legacy(&bytes) => {
+                    return Err(UnsupportedVersion {
+                        found: LEGACY_VERSION,
+                    })
+                }
+                None => {
+                    return Err(Corrupted {
+                        page_id: HEADER_PAGE_ID,
+                    })
+                }
+            };
+            manager.file_header.validate()?;
+            let mut header = Page::new(HEADER_PAGE_ID);
+            manager.read_slot(&mut header)?;
+            manager.header = header;
+        }
+        Ok(manager)
+    }
+
+    // Writes `header` over the header page of a data file that isn't open anywhere, unless the
+    // header page is intact. Returns whether it had to be repaired. A torn header page keeps the
+    // file from being opened at all, so this is how a saved copy is put back.
+    pub fn repair_header_page<P: AsRef<Path>>(path: P, header: &Page) -> Result<bool, PageError> {
+        let mut manager = FileDiskManager {
+            io: Box::new(BufferedIo::open(path.as_ref())?),
+            file_header: FileHeader::new(),
+            header: Page::new(HEADER_PAGE_ID),
+            slot: AlignedBuffer::new(BLOCK_SIZE),
+        };
+        let mut bytes = [0; FILE_HEADER_SIZE];
+        manager.io.read_at(&mut bytes, 0)?;
+        manager.file_header = FileHeader::decode(&bytes)?.ok_or(Corrupted {
+            page_id: HEADER_PAGE_ID,
+        })?;
+        let mut current = Page::new(HEADER_PAGE_ID);
+        match manager.read_slot(&mut current) {
+            Err(Corrupted { .. }) => {}
+            result => return result.map(|_| false),
+        }
+        manager.write_slot(header)?;
+        manager.io.sync()?;
+        Ok(true)
+    }
+
+    // Verifies a data file without opening it for writing: the file header, the header page
+    // holding the allocation map and the checksum of every allocated page. A file that can't be
+    // checked at all, e.g. because of an unreadable file header, is an error.
+    pub fn check<P: AsRef<Path>>(path: P) -> Result<CheckReport, PageError> {
+        let bytes = fs::read(path)?;
+        let file_header = match FileHeader::decode(&bytes)? {
+            Some(file_header) => file_header,
+            None if is_legacy(&bytes) => {
+                return Err(UnsupportedVersion {
+                    found: LEGACY_VERSION,
+                })
+            }
+            None => {
+                return Err(Corrupted {
+                    page_id: HEADER_PAGE_ID,
+                })
+            }
+        };
+        file_header.validate()?;
+        let mut report = CheckReport {
+            file_header,
+            allocated: Vec::new(),
+            free: Vec::new(),
+            problems: Vec::new(),
+        };
+
+        let slot =
+            |id: PageId| decode_slot(&bytes, file_header.slot_offset(id) as usize, PAGE_SIZE, id);
+        let header = match slot(HEADER_PAGE_ID) {
+            Ok(header) => header,
+            Err(e) => {
+                report.problems.push((HEADER_PAGE_ID, e));
+                return Ok(report);
+            }
+        };
+        // Neither the header page nor ids past the last page can be allocated.
+        let invalid_bits = (MAX_NUM_DISK_PAGES as usize + 1..PAGE_SIZE * 8)
+            .chain(Some(HEADER_PAGE_ID as usize))
+            .any(|id| is_set(&header.data, id));
+        if invalid_bits {
+            report.problems.push((
+                HEADER_PAGE_ID,
+                Corrupted {
+                    page_id: HEADER_PAGE_ID,
+                },
+            ));
+        }
+        for id in 1..=MAX_NUM_DISK_PAGES {
+            if !is_set(&header.data, id as usize) {
+                report.free.push(id);
+                continue;
+            }
+            report.allocated.push(id);
+            if let Err(e) = slot(id) {
+                report.problems.push((id, e));
+            }
+        }
+        Ok(report)
+    }
+
+    // Reads a page slot as is, including the header page as page 0, for diagnosing damaged
+    // files. Only the file header has to be readable. Ids that can't exist and slots past the end
+    // of the file are `PageNotFound`.
+    pub fn dump_page<P: AsRef<Path>>(path: P, id: PageId) -> Result<SlotDump, PageError> {
+        if !(HEADER_PAGE_ID..=MAX_NUM_DISK_PAGES).contains(&id) {
+            return Err(PageNotFound);
+        }
+        let bytes = fs::read(path)?;
+        let file_header = FileHeader::decode(&bytes)?.ok_or(Corrupted {
+            page_id: HEADER_PAGE_ID,
+        })?;
+        file_header.validate()?;
+        let start = file_header.slot_offset(id) as usize;
+        let slot = bytes.get(start..start + SLOT_SIZE).ok_or(PageNotFound)?;
+        let mut crc = [0; CHECKSUM_SIZE];
+        crc.copy_from_slice(&slot[PAGE_SIZE..]);
+        Ok(SlotDump {
+            data: slot[..PAGE_SIZE].to_vec(),
+            stored_checksum: u32::from_le_bytes(crc),
+            checksum: checksum(id, &slot[..PAGE_SIZE]),
+        })
+    }
+
+    // Compacts a data file that isn't open anywhere else.
+    pub fn compact_file<P: AsRef<Path>>(path: P) -> Result<Vec<(PageId, PageId)>, PageError> {
+        FileDiskManager::open(path)?.compact()
+    }
+
+    pub fn space_usage(&self) -> Result<SpaceUsage, PageError> {
+        let live_pages = self.pages_on_disk().len() as u64;
+        Ok(SpaceUsage {
+            logical_bytes: self.io.len()?,
+            allocated_bytes: self.io.allocated_len()?,
+            live_bytes: self.file_header.slot_offset(1)
+                + live_pages * self.file_header.slot_size() as u64,
+        })
+    }
+
+    pub fn file_header(&self) -> FileHeader {
+        self.file_header
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.io.is_direct()
+    }
+
+    fn is_allocated(&self, id: PageId) -> bool {
+        id > HEADER_PAGE_ID
+            && id <= MAX_NUM_DISK_PAGES
+            && is_set(&self.header.data, id as usize)
+    }
+
+    fn set_allocated(&mut self, id: PageId, allocated: bool) {
+        if allocated {
+            self.header.data[id as usize / 8] |= 1 << (id % 8);
+        } else {
+            self.header.data[id as usize / 8] &= !(1 << (id % 8));
+        }
+    }
+
+    // Punches the filesystem blocks that lie entirely within the run of free slots around `id`.
+    // A slot is much smaller than a block, so this only releases space after many neighbouring
+    // pages were deallocated. `compact` is what reliably shrinks the file.
+    fn punch_free_blocks(&mut self, id: PageId) -> Result<(), PageError> {
+        let mut first = id;
+        while first > 1 && !self.is_allocated(first - 1) {
+            first -= 1;
+        }
+        let mut last = id;
+        while last < MAX_NUM_DISK_PAGES && !self.is_allocated(last + 1) {
+            last += 1;
+        }
+        let block = BLOCK_SIZE as u64;
+        let start = self.file_header.slot_offset(first).next_multiple_of(block);
+        let end = self.file_header.slot_offset(last + 1).min(self.io.len()?) / block * block;
+        if start < end {
+            self.io.punch_hole(start, end - start)?;
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<(), PageError> {
+        let header = *self.header;
+        self.write_slot(&header)
+    }
+
+    fn read_slot(&mut self, page: &mut Page) -> Result<(), PageError> {
+        let slot = &mut self.slot[..self.file_header.slot_size()];
+        self.io
+            .read_at(slot, self.file_header.slot_offset(page.id))?;
+        page.data.copy_from_slice(&slot[..PAGE_SIZE]);
+        let mut checksum = [0; CHECKSUM_SIZE];
+        checksum.copy_from_slice(&slot[PAGE_SIZE..SLOT_SIZE]);
+        if u32::from_le_bytes(checksum) != page.checksum() {
+            return Err(Corrupted { page_id: page.id });
+        }
+        Ok(())
+    }
+
+    fn write_slot(&mut self, page: &Page) -> Result<(), PageError> {
+        let slot = &mut self.slot[..self.file_header.slot_size()];
+        encode_slot(page, slot);
+        self.io
+            .write_at(slot, self.file_header.slot_offset(page.id))?;
+        Ok(())
+    }
+}
+
+// Fills `slot` with the page data, its checksum and zeros after that.
+pub(crate) fn encode_slot(page: &Page, slot: &mut [u8]) {
+    slot[..PAGE_SIZE].copy_from_slice(&page.data);
+    slot[PAGE_SIZE..SLOT_SIZE].copy_from_slice(&page.checksum().to_le_bytes());
+    slot[SLOT_SIZE..].fill(0);
+}
+
+impl DiskManager for FileDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        if !self.is_allocated(id) {
+            return Err(PageNotFound);
+        }
+        page.id = id;
+        self.read_slot(page)
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        if !self.is_allocated(page.id) {
+            return Err(PageNotFound);
+        }
+        self.write_slot(page)
+    }
+
+    // The checksum is stored after the data, so a torn page fails it when read back.
+    fn write_page_torn(&mut self, page: &Page, bytes: usize) -> Result<(), PageError> {
+        if !self.is_allocated(page.id) {
+            return Err(PageNotFound);
+        }
+        let offset = self.file_header.slot_offset(page.id);
+        let slot = &mut self.slot[..self.file_header.slot_size()];
+        if self.file_header.flags & FLAG_BLOCK_SLOTS == 0 {
+            encode_slot(page, slot);
+            self.io.write_at(&slot[..bytes.min(SLOT_SIZE)], offset)?;
+            return Ok(());
+        }
+        // Only whole blocks can be written, so the rest of the slot is written back as it was.
+        let mut new = [0; SLOT_SIZE];
+        encode_slot(page, &mut new);
+        self.io.read_at(slot, offset)?;
+        let bytes = bytes.min(SLOT_SIZE);
+        slot[..bytes].copy_from_slice(&new[..bytes]);
+        self.io.write_at(slot, offset)?;
+        Ok(())
+    }
+
+    // Pages with consecutive ids are adjacent in the file, so each run of them is written with a
+    // single write.
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        if pages.iter().any(|page| !self.is_allocated(page.id)) {
+            return Err(PageNotFound);
+        }
+        let mut pages = pages.to_vec();
+        pages.sort_by_key(|page| page.id);
+        pages.dedup_by_key(|page| page.id);
+        let slot_size = self.file_header.slot_size();
+        let mut slots = AlignedBuffer::new(pages.len() * slot_size);
+        for (page, slot) in pages.iter().zip(slots.chunks_exact_mut(slot_size)) {
+            encode_slot(page, slot);
+        }
+        // Each run is a single buffer, so O_DIRECT can write it without copying it first.
+        let mut runs: Vec<(u64, std::ops::Range<usize>)> = Vec::new();
+        let mut previous_id = HEADER_PAGE_ID;
+        for (i, page) in pages.iter().enumerate() {
+            let slot = i * slot_size..(i + 1) * slot_size;
+            match runs.last_mut() {
+                Some((_, run)) if page.id == previous_id + 1 => run.end = slot.end,
+                _ => runs.push((self.file_header.slot_offset(page.id), slot)),
+            }
+            previous_id = page.id;
+        }
+        let runs: Vec<(u64, Vec<IoSlice>)> = runs
+            .into_iter()
+            .map(|(offset, run)| (offset, vec![IoSlice::new(&slots[run])]))
+            .collect();
+        self.io.write_runs(&runs)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.io.sync()?;
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = (1..=MAX_NUM_DISK_PAGES)
+            .find(|id| !self.is_allocated(*id))
+            .ok_or(OutOfStorage)?;
+        self.write_slot(&Page::new(id))?;
+        self.set_allocated(id, true);
+        if let Err(e) = self.write_header() {
+            self.set_allocated(id, false);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if !self.is_allocated(id) {
+            return Err(PageNotFound);
+        }
+        self.set_allocated(id, false);
+        if let Err(e) = self.write_header() {
+            self.set_allocated(id, true);
+            return Err(e);
+        }
+        // Once the header page is written the page is deallocated, whether or not any space could
+        // be released.
+        let _ = self.punch_free_blocks(id);
+        Ok(())
+    }
+
+    // Live pages past the end of the compacted file are copied into the slots that are free
+    // before it, and only then is the header page rewritten. No allocated slot is overwritten, so
+    // a crash before that leaves every page under its old id. The file is truncated after the
+    // last live page.
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        let live = self.pages_on_disk();
+        let end = live.len() as PageId;
+        let free = (1..=end).filter(|id| !self.is_allocated(*id));
+        let moves: Vec<(PageId, PageId)> = live
+            .iter()
+            .copied()
+            .filter(|id| *id > end)
+            .zip(free)
+            .collect();
+        let mut page = *Page::new(HEADER_PAGE_ID);
+        for (old_id, new_id) in moves.iter() {
+            page.id = *old_id;
+            self.read_slot(&mut page)?;
+            page.id = *new_id;
+            self.write_slot(&page)?;
+        }
+        if moves.is_empty()
+            && self.io.len()? <= self.file_header.slot_offset(live.len() as PageId + 1)
+        {
+            return Ok(moves);
+        }
+        self.io.sync()?;
+
+        let header = *self.header;
+        for id in 1..=MAX_NUM_DISK_PAGES {
+            self.set_allocated(id, id as usize <= live.len());
+        }
+        if let Err(e) = self.write_header() {
+            *self.header = header;
+            return Err(e);
+        }
+        // The pages now live under their new ids, so the moves must reach the caller even if
+        // the file can't be synced or shrunk. The old slots are only cut off once the header
+        // is known to be durable; otherwise a crash would bring back a header pointing at them.
+        if self.io.sync().is_ok() {
+            let end = self.file_header.slot_offset(live.len() as PageId + 1);
+            let _ = self.io.set_len(end);
+        }
+        Ok(moves)
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        (1..=MAX_NUM_DISK_PAGES)
+            .filter(|id| self.is_allocated(*id))
+            .collect()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        Some(*self.header)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::file_disk_manager::{
+        encode_slot, slot_offset, FileHeader, HEADER_PAGE_ID,
+    };
+    use crate::buffer_pool::file_io::{BufferedIo, FileIo};
+    use crate::buffer_pool::page::checksum;
+    use crate::buffer_pool::page::PageError::{
+        Corrupted, OutOfStorage, PageNotFound, PageSizeMismatch, UnsupportedFeatures,
+        UnsupportedVersion,
+    };
+    use crate::buffer_pool::tests::{corrupt_page, data_file, read};
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, FileDiskManager, Page, FLAG_BLOCK_SLOTS,
+        FLAG_PAGE_CHECKSUMS, FORMAT_VERSION, MAX_NUM_DISK_PAGES, PAGE_SIZE,
+    };
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::io::{ErrorKind, Seek, SeekFrom, Write};
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    fn patch_file_header(path: &Path, patch: impl FnOnce(&mut FileHeader)) {
+        let mut bytes = fs::read(path).unwrap();
+        let mut file_header = FileHeader::decode(&bytes).unwrap().unwrap();
+        patch(&mut file_header);
+        bytes[..64].copy_from_slice(&file_header.encode());
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn write_and_read_page() {
+        let path = data_file("write_and_read_page");
+        let mut dm = FileDiskManager::open(&path).unwrap();
+
+        let id = dm.allocate_page().unwrap();
+        assert_eq!(1, id);
+        assert_eq!([0; 8], read(&mut dm, id).unwrap().data);
+
+        let mut page = Page::new(id);
+        page.data.copy_from_slice(b"abcdefgh");
+        dm.write_page(&page).unwrap();
+        assert_eq!(*b"abcdefgh", read(&mut dm, id).unwrap().data);
+
+        assert_eq!(PageNotFound, read(&mut dm, 2).unwrap_err());
+        assert_eq!(PageNotFound, dm.write_page(&Page::new(2)).unwrap_err());
+    }
+
+    #[test]
+    fn reopen() {
+        let path = data_file("reopen");
+        {
+            let mut bpm = BufferPoolManager::new(FileDiskManager::open(&path).unwrap());
+            for i in 0..3u8 {
+                let page = bpm.new_page().unwrap();
+                page.data = [i + 1; 8];
+            }
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(vec![1, 2, 3], dm.pages_on_disk());
+        for i in 1..=3 {
+            assert_eq!([i as u8; 8], read(&mut dm, i).unwrap().data);
+        }
+        assert_eq!(4, dm.allocate_page().unwrap());
+    }
+
+    #[test]
+    fn deallocate_page() {
+        let path = data_file("deallocate_page");
+        let mut dm = FileDiskManager::open(&path).unwrap();
+
+        for _ in 0..MAX_NUM_DISK_PAGES {
+            dm.allocate_page().unwrap();
+        }
+        assert_eq!(OutOfStorage, dm.allocate_page().unwrap_err());
+
+        dm.deallocate_page(2).unwrap();
+        assert!(!dm.pages_on_disk().contains(&2));
+        assert_eq!(PageNotFound, read(&mut dm, 2).unwrap_err());
+        assert_eq!(PageNotFound, dm.deallocate_page(2).unwrap_err());
+
+        assert_eq!(2, dm.allocate_page().unwrap());
+        assert_eq!([0; 8], read(&mut dm, 2).unwrap().data);
+    }
+
+    #[test]
+    fn allocation_map_survives_reopen() {
+        let path = data_file("allocation_map_survives_reopen");
+        {
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            for _ in 0..4 {
+                dm.allocate_page().unwrap();
+            }
+            dm.deallocate_page(1).unwrap();
+            dm.deallocate_page(3).unwrap();
+        }
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(vec![2, 4], dm.pages_on_disk());
+        assert_eq!(1, dm.allocate_page().unwrap());
+        assert_eq!(3, dm.allocate_page().unwrap());
+        assert_eq!(5, dm.allocate_page().unwrap());
+    }
+
+    #[test]
+    fn detect_corrupted_page() {
+        let path = data_file("detect_corrupted_page");
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        let id = dm.allocate_page().unwrap();
+        let mut page = Page::new(id);
+        page.data.copy_from_slice(b"abcdefgh");
+        dm.write_page(&page).unwrap();
+
+        corrupt_page(&path, id);
+        assert_eq!(Corrupted { page_id: id }, read(&mut dm, id).unwrap_err());
+
+        let mut bpm = BufferPoolManager::new(dm);
+        assert_eq!(Corrupted { page_id: id }, bpm.fetch_page(id).unwrap_err());
+    }
+
+    #[test]
+    fn detect_corrupted_header() {
+        let path = data_file("detect_corrupted_header");
+        FileDiskManager::open(&path)
+            .unwrap()
+            .allocate_page()
+            .unwrap();
+
+        corrupt_page(&path, 0);
+        assert_eq!(
+            Corrupted { page_id: 0 },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn direct_io() {
+        let path = data_file("direct_io");
+        let direct = {
+            let mut dm = FileDiskManager::open_direct(&path).unwrap();
+            for i in 0..3u8 {
+                let mut page = Page::new(dm.allocate_page().unwrap());
+                page.data = [i + 1; 8];
+                dm.write_page(&page).unwrap();
+            }
+            assert_eq!([2; 8], read(&mut dm, 2).unwrap().data);
+            dm.is_direct()
+        };
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert!(!dm.is_direct());
+        // The file header, the header page and three pages, a block each.
+        if direct {
+            assert_eq!(FLAG_BLOCK_SLOTS, dm.file_header().flags & FLAG_BLOCK_SLOTS);
+            assert_eq!(5 * 4096, fs::metadata(&path).unwrap().len());
+        }
+        assert_eq!(vec![1, 2, 3], dm.pages_on_disk());
+        for i in 1..=3 {
+            assert_eq!([i as u8; 8], read(&mut dm, i).unwrap().data);
+        }
+    }
+
+    #[test]
+    fn direct_io_packed_slots() {
+        let path = data_file("direct_io_packed_slots");
+        FileDiskManager::open(&path)
+            .unwrap()
+            .allocate_page()
+            .unwrap();
+
+        let mut dm = FileDiskManager::open_direct(&path).unwrap();
+        assert!(!dm.is_direct());
+        assert_eq!(0, dm.file_header().flags & FLAG_BLOCK_SLOTS);
+        assert_eq!(2, dm.allocate_page().unwrap());
+    }
+
+    #[test]
+    fn block_slots() {
+        let path = data_file("block_slots");
+        let io = BufferedIo::open(&path).unwrap();
+        let flags = FLAG_PAGE_CHECKSUMS | FLAG_BLOCK_SLOTS;
+        let mut dm = FileDiskManager::open_with_flags(Box::new(io), flags).unwrap();
+        for i in 1..=3 {
+            let mut page = Page::new(dm.allocate_page().unwrap());
+            page.data = [i as u8; 8];
+            dm.write_pages(&[&page]).unwrap();
+        }
+        let mut page = Page::new(2);
+        page.data = [9; 8];
+        dm.write_page_torn(&page, 4).unwrap();
+        dm.deallocate_page(1).unwrap();
+        assert_eq!(vec![(3, 1)], dm.compact().unwrap());
+        drop(dm);
+
+        assert_eq!(4 * 4096, fs::metadata(&path).unwrap().len());
+        let report = FileDiskManager::check(&path).unwrap();
+        assert_eq!(vec![(2, Corrupted { page_id: 2 })], report.problems);
+        assert_eq!(
+            vec![3; 8],
+            FileDiskManager::dump_page(&path, 1).unwrap().data
+        );
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!([3; 8], read(&mut dm, 1).unwrap().data);
+    }
+
+    #[test]
+    fn uring_io() {
+        let path = data_file("uring_io");
+        {
+            let mut bpm = BufferPoolManager::new(FileDiskManager::open_uring(&path).unwrap());
+            for i in 0..3u8 {
+                let page = bpm.new_page().unwrap();
+                page.data = [i + 1; 8];
+            }
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let mut dm = FileDiskManager::open_uring(&path).unwrap();
+        assert_eq!(vec![1, 2, 3], dm.pages_on_disk());
+        for i in 1..=3 {
+            assert_eq!([i as u8; 8], read(&mut dm, i).unwrap().data);
+        }
+    }
+
+    #[test]
+    fn mmap_io() {
+        for sync_writes in [false, true] {
+            let path = data_file(&format!("mmap_io_{}", sync_writes));
+            {
+                let mut dm = FileDiskManager::open_mmap(&path, sync_writes).unwrap();
+                for i in 0..MAX_NUM_DISK_PAGES {
+                    let mut page = Page::new(dm.allocate_page().unwrap());
+                    page.data = [i as u8; 8];
+                    dm.write_page(&page).unwrap();
+                }
+                dm.deallocate_page(4).unwrap();
+                assert_eq!([2; 8], read(&mut dm, 3).unwrap().data);
+            }
+
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            assert!(!dm.pages_on_disk().contains(&4));
+            for i in 1..=MAX_NUM_DISK_PAGES {
+                if i != 4 {
+                    assert_eq!([i as u8 - 1; 8], read(&mut dm, i).unwrap().data);
+                }
+            }
+
+            let mut dm = FileDiskManager::open_mmap(&path, sync_writes).unwrap();
+            assert_eq!([0; 8], read(&mut dm, 1).unwrap().data);
+        }
+    }
+
+    #[test]
+    fn write_pages() {
+        for name in ["buffered", "uring"] {
+            let path = data_file(&format!("write_pages_{}", name));
+            let mut dm = match name {
+                "buffered" => FileDiskManager::open(&path).unwrap(),
+                _ => FileDiskManager::open_uring(&path).unwrap(),
+            };
+            for _ in 0..8 {
+                dm.allocate_page().unwrap();
+            }
+
+            let pages: Vec<Box<Page>> = [7, 2, 3, 8, 5, 1]
+                .iter()
+                .map(|id| {
+                    let mut page = Page::new(*id);
+                    page.data = [*id as u8; 8];
+                    page
+                })
+                .collect();
+            let batch: Vec<&Page> = pages.iter().map(|page| page.as_ref()).collect();
+            dm.write_pages(&batch).unwrap();
+
+            for id in [1, 2, 3, 5, 7, 8] {
+                assert_eq!([id as u8; 8], read(&mut dm, id).unwrap().data);
+            }
+            assert_eq!([0; 8], read(&mut dm, 4).unwrap().data);
+            assert_eq!(
+                PageNotFound,
+                dm.write_pages(&[&Page::new(1), &Page::new(9)]).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn file_header() {
+        let path = data_file("file_header");
+        let created = FileDiskManager::open(&path).unwrap().file_header().created;
+        let file_header = FileDiskManager::open(&path).unwrap().file_header();
+        assert_eq!(FORMAT_VERSION, file_header.version);
+        assert_eq!(PAGE_SIZE, file_header.page_size);
+        assert_eq!(FLAG_PAGE_CHECKSUMS, file_header.flags);
+        assert_eq!(created, file_header.created);
+        assert!(SystemTime::now().duration_since(created).unwrap() < Duration::from_secs(60));
+        assert_eq!(b"BPMDATA\0", &fs::read(&path).unwrap()[..8]);
+    }
+
+    #[test]
+    fn reject_incompatible_files() {
+        let path = data_file("reject_incompatible_files");
+        FileDiskManager::open(&path)
+            .unwrap()
+            .allocate_page()
+            .unwrap();
+
+        patch_file_header(&path, |file_header| file_header.page_size = 16);
+        assert_eq!(
+            PageSizeMismatch {
+                expected: 8,
+                found: 16
+            },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+        assert_eq!(
+            PageSizeMismatch {
+                expected: 8,
+                found: 16
+            },
+            FileDiskManager::upgrade(&path).unwrap_err()
+        );
+
+        patch_file_header(&path, |file_header| file_header.version = 3);
+        assert_eq!(
+            UnsupportedVersion { found: 3 },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+
+        patch_file_header(&path, |file_header| {
+            file_header.version = FORMAT_VERSION;
+            file_header.page_size = PAGE_SIZE;
+            file_header.flags |= 0x80;
+        });
+        assert_eq!(
+            UnsupportedFeatures { flags: 0x81 },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(8)).unwrap();
+        file.write_all(&[9]).unwrap();
+        assert_eq!(
+            Corrupted { page_id: 0 },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn upgrade_legacy_file() {
+        let path = data_file("upgrade_legacy_file");
+        // Version 1: the header page and pages 1 and 2, without a file header.
+        let mut header = Page::new(0);
+        header.data[0] = 0b110;
+        let mut bytes = vec![0; 3 * 12];
+        encode_slot(&header, &mut bytes[..12]);
+        for id in 1..=2 {
+            let mut page = Page::new(id);
+            page.data = [id as u8; 8];
+            encode_slot(&page, &mut bytes[id as usize * 12..(id as usize + 1) * 12]);
+        }
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(
+            UnsupportedVersion { found: 1 },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+        assert!(FileDiskManager::upgrade(&path).unwrap());
+        assert!(!FileDiskManager::upgrade(&path).unwrap());
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(FORMAT_VERSION, dm.file_header().version);
+        assert_eq!(vec![1, 2], dm.pages_on_disk());
+        assert_eq!([1; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!([2; 8], read(&mut dm, 2).unwrap().data);
+        assert_eq!(3, dm.allocate_page().unwrap());
+    }
+
+    #[test]
+    fn upgrade_page_size() {
+        let path = data_file("upgrade_page_size");
+        // Written with 4 byte pages: the header page and page 1.
+        let mut file_header = FileHeader::new();
+        file_header.page_size = 4;
+        let mut bytes = file_header.encode().to_vec();
+        for (id, data) in [(0, [0b10, 0, 0, 0]), (1, *b"abcd")] {
+            bytes.extend_from_slice(&data);
+            bytes.extend_from_slice(&checksum(id, &data).to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(
+            PageSizeMismatch {
+                expected: 8,
+                found: 4
+            },
+            FileDiskManager::open(&path).err().unwrap()
+        );
+        assert!(FileDiskManager::upgrade(&path).unwrap());
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(file_header.created, dm.file_header().created);
+        assert_eq!(vec![1], dm.pages_on_disk());
+        assert_eq!(*b"abcd\0\0\0\0", read(&mut dm, 1).unwrap().data);
+    }
+
+    #[test]
+    fn check() {
+        let path = data_file("check");
+        {
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            for _ in 0..3 {
+                dm.allocate_page().unwrap();
+            }
+            dm.deallocate_page(2).unwrap();
+        }
+        let report = FileDiskManager::check(&path).unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(FORMAT_VERSION, report.file_header.version);
+        assert_eq!(vec![1, 3], report.allocated);
+        assert_eq!(2, report.free[0]);
+        assert_eq!(MAX_NUM_DISK_PAGES as usize - 2, report.free.len());
+
+        corrupt_page(&path, 3);
+        let report = FileDiskManager::check(&path).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(vec![(3, Corrupted { page_id: 3 })], report.problems);
+
+        // A valid header page that claims the header page itself is allocated.
+        let mut bytes = fs::read(&path).unwrap();
+        let header = slot_offset(0) as usize;
+        bytes[header] |= 1;
+        let crc = checksum(0, &bytes[header..header + PAGE_SIZE]);
+        bytes[header + PAGE_SIZE..header + PAGE_SIZE + 4].copy_from_slice(&crc.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let report = FileDiskManager::check(&path).unwrap();
+        assert_eq!((0, Corrupted { page_id: 0 }), report.problems[0]);
+
+        corrupt_page(&path, 0);
+        let report = FileDiskManager::check(&path).unwrap();
+        assert!(report.allocated.is_empty());
+        assert_eq!(vec![(0, Corrupted { page_id: 0 })], report.problems);
+
+        fs::remove_file(&path).unwrap();
+        assert!(FileDiskManager::check(&path).is_err());
+    }
+
+    #[test]
+    fn dump_page() {
+        let path = data_file("dump_page");
+        {
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            let mut page = Page::new(dm.allocate_page().unwrap());
+            page.data.copy_from_slice(b"abcdefgh");
+            dm.write_page(&page).unwrap();
+        }
+        let dump = FileDiskManager::dump_page(&path, 1).unwrap();
+        assert_eq!(b"abcdefgh".to_vec(), dump.data);
+        assert_eq!(dump.checksum, dump.stored_checksum);
+
+        corrupt_page(&path, 1);
+        let dump = FileDiskManager::dump_page(&path, 1).unwrap();
+        assert_eq!(0xff, dump.data[3]);
+        assert_ne!(dump.checksum, dump.stored_checksum);
+        for id in [-1, MAX_NUM_DISK_PAGES + 1, i32::MAX] {
+            assert_eq!(
+                PageNotFound,
+                FileDiskManager::dump_page(&path, id).unwrap_err()
+            );
+        }
+        // A valid id, but the file ends before its slot.
+        assert_eq!(
+            PageNotFound,
+            FileDiskManager::dump_page(&path, 2).unwrap_err()
+        );
+
+        patch_file_header(&path, |file_header| file_header.version = 3);
+        assert_eq!(
+            UnsupportedVersion { found: 3 },
+            FileDiskManager::dump_page(&path, 1).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn keep_shared_blocks_on_deallocate() {
+        let path = data_file("keep_shared_blocks_on_deallocate");
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        for i in 1..=3 {
+            let mut page = Page::new(dm.allocate_page().unwrap());
+            page.data = [i as u8; 8];
+            dm.write_page(&page).unwrap();
+        }
+
+        dm.deallocate_page(2).unwrap();
+        // The slot shares its block with live pages, so nothing is punched.
+        let bytes = fs::read(&path).unwrap();
+        let slot = slot_offset(2) as usize;
+        assert_eq!([2; 8], bytes[slot..slot + 8]);
+        assert_eq!([3; 8], read(&mut dm, 3).unwrap().data);
+        assert_eq!(2, dm.allocate_page().unwrap());
+        assert_eq!([0; 8], read(&mut dm, 2).unwrap().data);
+        dm.deallocate_page(2).unwrap();
+
+        let usage = dm.space_usage().unwrap();
+        assert_eq!(bytes.len() as u64, usage.logical_bytes);
+        assert_eq!(slot_offset(1) + 2 * 12, usage.live_bytes);
+    }
+
+    #[test]
+    fn compact() {
+        let path = data_file("compact");
+        {
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            for i in 1..=6 {
+                let mut page = Page::new(dm.allocate_page().unwrap());
+                page.data = [i as u8; 8];
+                dm.write_page(&page).unwrap();
+            }
+            for id in [1, 3, 4] {
+                dm.deallocate_page(id).unwrap();
+            }
+        }
+
+        assert_eq!(
+            vec![(5, 1), (6, 3)],
+            FileDiskManager::compact_file(&path).unwrap()
+        );
+        assert_eq!(slot_offset(4), fs::metadata(&path).unwrap().len());
+
+        let mut dm = FileDiskManager::open_mmap(&path, false).unwrap();
+        assert_eq!(vec![1, 2, 3], dm.pages_on_disk());
+        for (id, data) in [(1, 5), (2, 2), (3, 6)] {
+            assert_eq!([data; 8], read(&mut dm, id).unwrap().data);
+        }
+        assert!(dm.compact().unwrap().is_empty());
+
+        dm.deallocate_page(1).unwrap();
+        assert_eq!(vec![(3, 1)], dm.compact().unwrap());
+        assert_eq!([6; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!(3, dm.allocate_page().unwrap());
+        assert_eq!([0; 8], read(&mut dm, 3).unwrap().data);
+    }
+
+    // Fails every write to the header page, as if the process died right before it.
+    struct CrashBeforeHeader(BufferedIo);
+
+    impl FileIo for CrashBeforeHeader {
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            self.0.read_at(buf, offset)
+        }
+
+        fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+            if offset == slot_offset(HEADER_PAGE_ID) {
+                return Err(io::Error::from(ErrorKind::Interrupted));
+            }
+            self.0.write_at(buf, offset)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.0.sync()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.0.len()
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.0.set_len(len)
+        }
+
+        fn allocated_len(&self) -> io::Result<u64> {
+            self.0.allocated_len()
+        }
+    }
+
+    #[test]
+    fn crash_during_compact() {
+        let path = data_file("crash_during_compact");
+        {
+            let mut dm = FileDiskManager::open(&path).unwrap();
+            for i in 1..=6 {
+                let mut page = Page::new(dm.allocate_page().unwrap());
+                page.data = [i as u8; 8];
+                dm.write_page(&page).unwrap();
+            }
+            for id in [1, 3] {
+                dm.deallocate_page(id).unwrap();
+            }
+        }
+
+        let io = CrashBeforeHeader(BufferedIo::open(&path).unwrap());
+        let mut dm = FileDiskManager::open_with(Box::new(io)).unwrap();
+        assert!(dm.compact().is_err());
+        drop(dm);
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(vec![2, 4, 5, 6], dm.pages_on_disk());
+        for id in [2, 4, 5, 6] {
+            assert_eq!([id as u8; 8], read(&mut dm, id).unwrap().data);
+        }
+    }
+
+    // Fails every attempt to shrink the file.
+    struct NoTruncate(BufferedIo);
+
+    impl FileIo for NoTruncate {
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            self.0.read_at(buf, offset)
+        }
+
+        fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+            self.0.write_at(buf, offset)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.0.sync()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            self.0.len()
+        }
+
+        fn set_len(&mut self, _len: u64) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::PermissionDenied))
+        }
+
+        fn allocated_len(&self) -> io::Result<u64> {
+            self.0.allocated_len()
+        }
+    }
+
+    #[test]
+    fn compact_without_truncate() {
+        let path = data_file("compact_without_truncate");
+        let io = NoTruncate(BufferedIo::open(&path).unwrap());
+        let mut dm = FileDiskManager::open_with(Box::new(io)).unwrap();
+        for i in 1..=3 {
+            let mut page = Page::new(dm.allocate_page().unwrap());
+            page.data = [i as u8; 8];
+            dm.write_page(&page).unwrap();
+        }
+        dm.deallocate_page(1).unwrap();
+
+        assert_eq!(vec![(3, 1)], dm.compact().unwrap());
+        drop(dm);
+
+        let mut dm = FileDiskManager::open(&path).unwrap();
+        assert_eq!(vec![1, 2], dm.pages_on_disk());
+        assert_eq!([3; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!([2; 8], read(&mut dm, 2).unwrap().data);
+    }
+}
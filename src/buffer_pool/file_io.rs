@@ -0,0 +1,663 @@
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::IoSlice;
+#[cfg(not(unix))]
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::Path;
+use std::slice;
+
+// Positioned I/O on the data file, so the on-disk format in `FileDiskManager` doesn't depend on
+// how the bytes actually reach the disk.
+pub trait FileIo: Send {
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()>;
+    // Writes each run's buffers back to back, starting at the run's offset.
+    fn write_runs(&mut self, runs: &[(u64, Vec<IoSlice>)]) -> io::Result<()> {
+        for (offset, bufs) in runs {
+            self.write_at(&concat(bufs), *offset)?;
+        }
+        Ok(())
+    }
+    // Releases the storage behind a range of the file, which reads as zeros afterwards.
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        self.write_at(&vec![0; len as usize], offset)
+    }
+    fn sync(&mut self) -> io::Result<()>;
+    fn len(&self) -> io::Result<u64>;
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+    // Bytes actually allocated on disk, less than `len` for sparse files.
+    fn allocated_len(&self) -> io::Result<u64>;
+    fn is_direct(&self) -> bool {
+        false
+    }
+}
+
+fn concat(bufs: &[IoSlice]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for slice in bufs {
+        buf.extend_from_slice(slice);
+    }
+    buf
+}
+
+#[cfg(unix)]
+fn allocated_len(file: &File) -> io::Result<u64> {
+    Ok(file.metadata()?.blocks() * 512)
+}
+
+// Without block counts the whole file is assumed to be allocated.
+#[cfg(not(unix))]
+fn allocated_len(file: &File) -> io::Result<u64> {
+    Ok(file.metadata()?.len())
+}
+
+// Returns false when the filesystem doesn't support punching holes.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if result == 0 {
+        Ok(true)
+    } else {
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) => Ok(false),
+            _ => Err(e),
+        }
+    }
+}
+
+fn open_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    options
+}
+
+// O_DIRECT requires the buffer, offset and length of every transfer to be aligned to the logical
+// block size of the device. 4096 covers both 512 byte and 4K sector devices, and is also the
+// filesystem block size that hole punching assumes.
+pub const BLOCK_SIZE: usize = 4096;
+
+// A zeroed buffer of `len` bytes that starts on a block boundary.
+pub struct AlignedBuffer {
+    ptr: *mut u8,
+    len: usize,
+    layout: Layout,
+}
+
+// The buffer is exclusively owned, like a `Box<[u8]>`.
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(len: usize) -> AlignedBuffer {
+        let layout = Layout::from_size_align(len.max(1).next_multiple_of(BLOCK_SIZE), BLOCK_SIZE)
+            .expect("invalid buffer layout");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        AlignedBuffer { ptr, len, layout }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+pub struct BufferedIo {
+    file: File,
+}
+
+impl BufferedIo {
+    pub fn open(path: &Path) -> io::Result<BufferedIo> {
+        Ok(BufferedIo {
+            file: open_options().open(path)?,
+        })
+    }
+}
+
+impl FileIo for BufferedIo {
+    #[cfg(unix)]
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.file.read_exact_at(buf, offset)
+    }
+
+    #[cfg(not(unix))]
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.file.write_all_at(buf, offset)
+    }
+
+    #[cfg(not(unix))]
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(buf)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_runs(&mut self, runs: &[(u64, Vec<IoSlice>)]) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        for (offset, bufs) in runs {
+            // `IoSlice` is guaranteed to be ABI compatible with `iovec`.
+            let written = unsafe {
+                libc::pwritev(
+                    self.file.as_raw_fd(),
+                    bufs.as_ptr() as *const libc::iovec,
+                    bufs.len() as libc::c_int,
+                    *offset as libc::off_t,
+                )
+            };
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = written as usize;
+            let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+            if written < len {
+                let buf = concat(bufs);
+                self.write_at(&buf[written..], offset + written as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        if !punch_hole(&self.file, offset, len)? {
+            self.write_at(&vec![0; len as usize], offset)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    fn allocated_len(&self) -> io::Result<u64> {
+        allocated_len(&self.file)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use direct::DirectIo;
+#[cfg(target_os = "linux")]
+pub use mmap::MmapIo;
+#[cfg(target_os = "linux")]
+pub use uring::UringIo;
+
+#[cfg(target_os = "linux")]
+mod direct {
+    use crate::buffer_pool::file_io::{
+        allocated_len, concat, open_options, punch_hole, AlignedBuffer, FileIo, BLOCK_SIZE,
+    };
+    use std::fs::File;
+    use std::io;
+    use std::io::IoSlice;
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+    use std::path::Path;
+
+    // Transfers go straight between the disk and the caller's buffer, so writes have to cover
+    // whole blocks. Buffers that aren't aligned in memory are copied into an aligned one first.
+    // Reads of partial blocks, like the file header, read the blocks around them.
+    pub struct DirectIo {
+        file: File,
+        buffer: AlignedBuffer,
+    }
+
+    impl DirectIo {
+        pub fn open(path: &Path) -> io::Result<DirectIo> {
+            let file = open_options().custom_flags(libc::O_DIRECT).open(path)?;
+            let mut direct = DirectIo {
+                file,
+                buffer: AlignedBuffer::new(BLOCK_SIZE),
+            };
+            // Some filesystems accept the flag on open and only reject the aligned I/O itself.
+            direct.read_blocks(0, BLOCK_SIZE)?;
+            Ok(direct)
+        }
+
+        fn is_aligned(buf: &[u8], offset: u64) -> bool {
+            (buf.as_ptr() as usize).is_multiple_of(BLOCK_SIZE)
+                && DirectIo::covers_blocks(buf, offset)
+        }
+
+        fn covers_blocks(buf: &[u8], offset: u64) -> bool {
+            buf.len().is_multiple_of(BLOCK_SIZE) && offset.is_multiple_of(BLOCK_SIZE as u64)
+        }
+
+        fn block_range(offset: u64, len: usize) -> (u64, usize) {
+            let alignment = BLOCK_SIZE as u64;
+            let start = offset - offset % alignment;
+            let end = (offset + len as u64).div_ceil(alignment) * alignment;
+            (start, (end - start) as usize)
+        }
+
+        fn reserve(&mut self, len: usize) {
+            if self.buffer.len() < len {
+                self.buffer = AlignedBuffer::new(len);
+            }
+        }
+
+        fn read_blocks(&mut self, start: u64, len: usize) -> io::Result<()> {
+            self.reserve(len);
+            let mut read = 0;
+            while read < len {
+                match self
+                    .file
+                    .read_at(&mut self.buffer[read..len], start + read as u64)?
+                {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            // Anything past the end of the file reads as zeros.
+            for byte in self.buffer[read..len].iter_mut() {
+                *byte = 0;
+            }
+            Ok(())
+        }
+    }
+
+    impl FileIo for DirectIo {
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            if offset + buf.len() as u64 > self.len()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if DirectIo::is_aligned(buf, offset) {
+                return self.file.read_exact_at(buf, offset);
+            }
+            let (start, len) = DirectIo::block_range(offset, buf.len());
+            self.read_blocks(start, len)?;
+            let skip = (offset - start) as usize;
+            buf.copy_from_slice(&self.buffer[skip..skip + buf.len()]);
+            Ok(())
+        }
+
+        fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+            if DirectIo::is_aligned(buf, offset) {
+                return self.file.write_all_at(buf, offset);
+            }
+            if !DirectIo::covers_blocks(buf, offset) {
+                return Err(io::ErrorKind::InvalidInput.into());
+            }
+            self.reserve(buf.len());
+            self.buffer[..buf.len()].copy_from_slice(buf);
+            self.file.write_all_at(&self.buffer[..buf.len()], offset)
+        }
+
+        fn write_runs(&mut self, runs: &[(u64, Vec<IoSlice>)]) -> io::Result<()> {
+            for (offset, bufs) in runs {
+                match bufs.as_slice() {
+                    [buf] => self.write_at(buf, *offset)?,
+                    bufs => self.write_at(&concat(bufs), *offset)?,
+                }
+            }
+            Ok(())
+        }
+
+        fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            if !punch_hole(&self.file, offset, len)? {
+                self.write_at(&vec![0; len as usize], offset)?;
+            }
+            Ok(())
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.file.sync_data()
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            Ok(self.file.metadata()?.len())
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+
+        fn allocated_len(&self) -> io::Result<u64> {
+            allocated_len(&self.file)
+        }
+
+        fn is_direct(&self) -> bool {
+            true
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod uring {
+    use crate::buffer_pool::file_io::{allocated_len, concat, open_options, FileIo};
+    use io_uring::{opcode, squeue, types, IoUring};
+    use std::fs::File;
+    use std::io;
+    use std::io::IoSlice;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::process;
+
+    const QUEUE_DEPTH: u32 = 64;
+
+    fn is_transient(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY)
+        )
+    }
+
+    // Every read and write is a submission to the ring followed by a wait for its completion.
+    // Batches of runs are pushed to the submission queue together as vectored writes and
+    // submitted with a single syscall.
+    pub struct UringIo {
+        file: File,
+        ring: IoUring,
+        // Sequence number of the current batch, in the upper half of each entry's user data.
+        batch: u64,
+    }
+
+    impl UringIo {
+        pub fn open(path: &Path) -> io::Result<UringIo> {
+            Ok(UringIo {
+                file: open_options().open(path)?,
+                ring: IoUring::new(QUEUE_DEPTH)?,
+                batch: 0,
+            })
+        }
+
+        fn fd(&self) -> types::Fd {
+            types::Fd(self.file.as_raw_fd())
+        }
+
+        // Submits the entries, waits for all of them and returns their results in order. The
+        // buffers referenced by the entries must stay alive until this returns, so it doesn't
+        // return while any entry the kernel took is still in flight, not even on errors.
+        fn submit(&mut self, entries: &[squeue::Entry]) -> io::Result<Vec<i32>> {
+            self.batch += 1;
+            let mut results = vec![None; entries.len()];
+            let mut pushed = 0;
+            let mut error = None;
+            for (i, entry) in entries.iter().enumerate() {
+                let entry = entry.clone().user_data(self.batch << 32 | i as u64);
+                if unsafe { self.ring.submission().push(&entry) }.is_err() {
+                    error = Some(io::Error::other("submission queue is full"));
+                    break;
+                }
+                pushed += 1;
+            }
+
+            let (mut submitted, mut completed) = (0, 0);
+            while completed < pushed {
+                let result = self.ring.submit_and_wait(1);
+                // Completions of earlier batches can't be matched to their entries anymore.
+                for cqe in self.ring.completion() {
+                    if cqe.user_data() >> 32 == self.batch {
+                        results[(cqe.user_data() & u32::MAX as u64) as usize] = Some(cqe.result());
+                        completed += 1;
+                    }
+                }
+                match result {
+                    Ok(n) => submitted += n,
+                    Err(e) if is_transient(&e) => {}
+                    Err(e) if submitted == completed => {
+                        // Nothing is in flight, and a new ring makes sure the entries the kernel
+                        // didn't take are never submitted after their buffers are gone.
+                        self.ring = IoUring::new(QUEUE_DEPTH)?;
+                        return Err(e);
+                    }
+                    // Returning, or unwinding, would free buffers the kernel may still be using.
+                    Err(e) => {
+                        eprintln!("io_uring failed with requests in flight: {}", e);
+                        process::abort();
+                    }
+                }
+            }
+            match error {
+                Some(e) => Err(e),
+                None => Ok(results.into_iter().map(Option::unwrap).collect()),
+            }
+        }
+
+        fn check(result: i32) -> io::Result<usize> {
+            if result < 0 {
+                Err(io::Error::from_raw_os_error(-result))
+            } else {
+                Ok(result as usize)
+            }
+        }
+    }
+
+    impl FileIo for UringIo {
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            let mut done = 0;
+            while done < buf.len() {
+                let rest = &mut buf[done..];
+                let entry = opcode::Read::new(self.fd(), rest.as_mut_ptr(), rest.len() as u32)
+                    .offset(offset + done as u64)
+                    .build();
+                match UringIo::check(self.submit(&[entry])?[0])? {
+                    0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    n => done += n,
+                }
+            }
+            Ok(())
+        }
+
+        fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+            let mut done = 0;
+            while done < buf.len() {
+                let rest = &buf[done..];
+                let entry = opcode::Write::new(self.fd(), rest.as_ptr(), rest.len() as u32)
+                    .offset(offset + done as u64)
+                    .build();
+                match UringIo::check(self.submit(&[entry])?[0])? {
+                    0 => return Err(io::ErrorKind::WriteZero.into()),
+                    n => done += n,
+                }
+            }
+            Ok(())
+        }
+
+        fn write_runs(&mut self, runs: &[(u64, Vec<IoSlice>)]) -> io::Result<()> {
+            for chunk in runs.chunks(QUEUE_DEPTH as usize) {
+                let entries: Vec<squeue::Entry> = chunk
+                    .iter()
+                    .map(|(offset, bufs)| {
+                        // `IoSlice` is guaranteed to be ABI compatible with `iovec`.
+                        opcode::Writev::new(
+                            self.fd(),
+                            bufs.as_ptr() as *const libc::iovec,
+                            bufs.len() as u32,
+                        )
+                        .offset(*offset)
+                        .build()
+                    })
+                    .collect();
+                let results = self.submit(&entries)?;
+                for ((offset, bufs), result) in chunk.iter().zip(results) {
+                    let written = UringIo::check(result)?;
+                    let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+                    if written < len {
+                        let buf = concat(bufs);
+                        self.write_at(&buf[written..], offset + written as u64)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            let entry = opcode::Fsync::new(self.fd())
+                .flags(types::FsyncFlags::DATASYNC)
+                .build();
+            UringIo::check(self.submit(&[entry])?[0])?;
+            Ok(())
+        }
+
+        fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            let entry = opcode::Fallocate::new(self.fd(), len)
+                .offset(offset)
+                .mode(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE)
+                .build();
+            match UringIo::check(self.submit(&[entry])?[0]) {
+                Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                    self.write_at(&vec![0; len as usize], offset)
+                }
+                result => result.map(|_| ()),
+            }
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            Ok(self.file.metadata()?.len())
+        }
+
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+
+        fn allocated_len(&self) -> io::Result<u64> {
+            allocated_len(&self.file)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod mmap {
+    use crate::buffer_pool::file_io::{allocated_len, open_options, punch_hole, FileIo};
+    use memmap2::MmapMut;
+    use std::fs::File;
+    use std::io;
+    use std::path::Path;
+
+    // The file grows in steps of at least this many bytes so that allocating pages one at a
+    // time doesn't remap on every allocation.
+    const GROWTH: u64 = 4096;
+
+    // Reads copy out of a shared mapping of the data file and writes copy into it, optionally
+    // followed by an msync of the written range.
+    pub struct MmapIo {
+        file: File,
+        map: Option<MmapMut>,
+        sync_writes: bool,
+    }
+
+    impl MmapIo {
+        pub fn open(path: &Path, sync_writes: bool) -> io::Result<MmapIo> {
+            let mut mmap = MmapIo {
+                file: open_options().open(path)?,
+                map: None,
+                sync_writes,
+            };
+            mmap.remap()?;
+            Ok(mmap)
+        }
+
+        fn remap(&mut self) -> io::Result<()> {
+            self.map = None;
+            if self.file.metadata()?.len() > 0 {
+                self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+            }
+            Ok(())
+        }
+
+        fn mapped_len(&self) -> u64 {
+            self.map.as_ref().map_or(0, |map| map.len() as u64)
+        }
+    }
+
+    impl FileIo for MmapIo {
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            let end = offset + buf.len() as u64;
+            match self.map.as_ref() {
+                Some(map) if end <= map.len() as u64 => {
+                    buf.copy_from_slice(&map[offset as usize..end as usize]);
+                    Ok(())
+                }
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            }
+        }
+
+        fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+            let end = offset + buf.len() as u64;
+            if end > self.mapped_len() {
+                let len = end.max(self.mapped_len() * 2).div_ceil(GROWTH) * GROWTH;
+                self.file.set_len(len)?;
+                self.remap()?;
+            }
+            if let Some(map) = self.map.as_mut() {
+                map[offset as usize..end as usize].copy_from_slice(buf);
+                if self.sync_writes {
+                    map.flush_range(offset as usize, buf.len())?;
+                }
+            }
+            Ok(())
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            match self.map.as_ref() {
+                Some(map) => map.flush(),
+                None => Ok(()),
+            }
+        }
+
+        // The mapping is shared, so it sees the hole like any other reader of the file.
+        fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            if !punch_hole(&self.file, offset, len)? {
+                self.write_at(&vec![0; len as usize], offset)?;
+            }
+            Ok(())
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            Ok(self.file.metadata()?.len())
+        }
+
+        // Pages past the end of the file can't stay mapped, accessing them raises SIGBUS.
+        fn set_len(&mut self, len: u64) -> io::Result<()> {
+            self.map = None;
+            self.file.set_len(len)?;
+            self.remap()
+        }
+
+        fn allocated_len(&self) -> io::Result<u64> {
+            allocated_len(&self.file)
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, PAGE_SIZE};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+// Bucket `i` counts operations that took less than `2^i` microseconds, the last bucket counts
+// everything slower.
+pub const LATENCY_BUCKETS: usize = 21;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LatencyHistogram {
+    #[serde(rename = "Count")]
+    pub count: u64,
+    #[serde(rename = "TotalMicros")]
+    pub total_micros: u64,
+    #[serde(rename = "MaxMicros")]
+    pub max_micros: u64,
+    #[serde(rename = "Buckets")]
+    pub buckets: Vec<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            count: 0,
+            total_micros: 0,
+            max_micros: 0,
+            buckets: vec![0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let bucket = (64 - micros.leading_zeros() as usize).min(LATENCY_BUCKETS - 1);
+        self.count += 1;
+        self.total_micros += micros;
+        self.max_micros = self.max_micros.max(micros);
+        self.buckets[bucket] += 1;
+    }
+}
+
+// Operation counters only include successful operations, failed ones are counted in `errors`.
+// Latencies are recorded either way. A batch adds every page to the counters, but only takes one
+// latency sample.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct IoStats {
+    #[serde(rename = "Reads")]
+    pub reads: u64,
+    #[serde(rename = "Writes")]
+    pub writes: u64,
+    #[serde(rename = "BytesRead")]
+    pub bytes_read: u64,
+    #[serde(rename = "BytesWritten")]
+    pub bytes_written: u64,
+    #[serde(rename = "Allocations")]
+    pub allocations: u64,
+    #[serde(rename = "Deallocations")]
+    pub deallocations: u64,
+    #[serde(rename = "Syncs")]
+    pub syncs: u64,
+    #[serde(rename = "Errors")]
+    pub errors: u64,
+    #[serde(rename = "ReadLatency")]
+    pub read_latency: LatencyHistogram,
+    #[serde(rename = "WriteLatency")]
+    pub write_latency: LatencyHistogram,
+    #[serde(rename = "AllocateLatency")]
+    pub allocate_latency: LatencyHistogram,
+    #[serde(rename = "DeallocateLatency")]
+    pub deallocate_latency: LatencyHistogram,
+    #[serde(rename = "SyncLatency")]
+    pub sync_latency: LatencyHistogram,
+}
+
+// Wraps another disk manager and keeps I/O statistics for everything that goes through it.
+pub struct InstrumentedDiskManager<D: DiskManager> {
+    inner: D,
+    stats: IoStats,
+}
+
+impl<D: DiskManager> InstrumentedDiskManager<D> {
+    pub fn new(inner: D) -> Box<InstrumentedDiskManager<D>> {
+        Box::new(InstrumentedDiskManager {
+            inner,
+            stats: IoStats::default(),
+        })
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> &IoStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = IoStats::default();
+    }
+
+    fn measure<T>(
+        &mut self,
+        histogram: fn(&mut IoStats) -> &mut LatencyHistogram,
+        operation: impl FnOnce(&mut D) -> Result<T, PageError>,
+    ) -> Result<T, PageError> {
+        let start = Instant::now();
+        let result = operation(&mut self.inner);
+        histogram(&mut self.stats).record(start.elapsed());
+        if result.is_err() {
+            self.stats.errors += 1;
+        }
+        result
+    }
+}
+
+impl<D: DiskManager + 'static> DiskManager for InstrumentedDiskManager<D> {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        self.measure(
+            |stats| &mut stats.read_latency,
+            |inner| inner.read_page(id, page),
+        )?;
+        self.stats.reads += 1;
+        self.stats.bytes_read += PAGE_SIZE as u64;
+        Ok(())
+    }
+
+    fn read_pages(&mut self, ids: &[PageId], pages: &mut [Page]) -> Result<(), PageError> {
+        self.measure(
+            |stats| &mut stats.read_latency,
+            |inner| inner.read_pages(ids, pages),
+        )?;
+        self.stats.reads += ids.len() as u64;
+        self.stats.bytes_read += (ids.len() * PAGE_SIZE) as u64;
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        self.measure(
+            |stats| &mut stats.write_latency,
+            |inner| inner.write_page(page),
+        )?;
+        self.stats.writes += 1;
+        self.stats.bytes_written += PAGE_SIZE as u64;
+        Ok(())
+    }
+
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        self.measure(
+            |stats| &mut stats.write_latency,
+            |inner| inner.write_pages(pages),
+        )?;
+        self.stats.writes += pages.len() as u64;
+        self.stats.bytes_written += (pages.len() * PAGE_SIZE) as u64;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.measure(|stats| &mut stats.sync_latency, |inner| inner.sync())?;
+        self.stats.syncs += 1;
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = self.measure(
+            |stats| &mut stats.allocate_latency,
+            |inner| inner.allocate_page(),
+        )?;
+        self.stats.allocations += 1;
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        self.measure(
+            |stats| &mut stats.deallocate_latency,
+            |inner| inner.deallocate_page(id),
+        )?;
+        self.stats.deallocations += 1;
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        self.inner.compact()
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.inner.pages_on_disk()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        self.inner.header_page()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::instrumented_disk_manager::LATENCY_BUCKETS;
+    use crate::buffer_pool::tests::data_file;
+    use crate::buffer_pool::{
+        DiskManager, DiskManagerMock, FileDiskManager, InstrumentedDiskManager, LatencyHistogram,
+        Page,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn count_operations() {
+        let mut dm = InstrumentedDiskManager::new(DiskManagerMock::new());
+        let id = dm.allocate_page().unwrap();
+        dm.write_page(&Page::new(id)).unwrap();
+        dm.write_pages(&[&Page::new(id), &Page::new(id)]).unwrap();
+        let mut page = *Page::new(0);
+        dm.read_page(id, &mut page).unwrap();
+        assert!(dm.read_page(id + 1, &mut page).is_err());
+        dm.deallocate_page(id).unwrap();
+        dm.sync().unwrap();
+
+        let stats = dm.stats();
+        assert_eq!(1, stats.allocations);
+        assert_eq!(3, stats.writes);
+        assert_eq!(24, stats.bytes_written);
+        assert_eq!(1, stats.reads);
+        assert_eq!(8, stats.bytes_read);
+        assert_eq!(1, stats.deallocations);
+        assert_eq!(1, stats.syncs);
+        assert_eq!(1, stats.errors);
+        assert_eq!(2, stats.write_latency.count);
+        assert_eq!(2, stats.read_latency.count);
+
+        dm.reset_stats();
+        assert_eq!(0, dm.stats().writes);
+    }
+
+    #[test]
+    fn latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_nanos(500));
+        histogram.record(Duration::from_micros(1));
+        histogram.record(Duration::from_micros(3));
+        histogram.record(Duration::from_micros(4));
+        histogram.record(Duration::from_secs(10));
+
+        assert_eq!(5, histogram.count);
+        assert_eq!(10_000_008, histogram.total_micros);
+        assert_eq!(10_000_000, histogram.max_micros);
+        assert_eq!(1, histogram.buckets[0]);
+        assert_eq!(1, histogram.buckets[1]);
+        assert_eq!(1, histogram.buckets[2]);
+        assert_eq!(1, histogram.buckets[3]);
+        assert_eq!(1, histogram.buckets[LATENCY_BUCKETS - 1]);
+    }
+
+    #[test]
+    fn header_page() {
+        let path = data_file("instrumented_header_page");
+        let mut dm = InstrumentedDiskManager::new(FileDiskManager::open(&path).unwrap());
+        dm.allocate_page().unwrap();
+        assert_eq!(0b10, dm.header_page().unwrap().data[0]);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::buffer_pool::object_store_disk_manager::CHECKSUM_HEADER;
+use crate::buffer_pool::PageError;
+use crate::buffer_pool::PageError::IoError;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::{self, Runtime};
+use tokio::sync::oneshot;
+
+struct Object {
+    data: Vec<u8>,
+    checksum: Option<String>,
+}
+
+type Objects = Arc<Mutex<BTreeMap<String, Object>>>;
+
+// A minimal in-memory stand-in for an S3 compatible object store, serving the requests the
+// `ObjectStoreDiskManager` makes on a random local port. Buckets are created on first use.
+pub struct LocalObjectServer {
+    runtime: Option<Runtime>,
+    addr: SocketAddr,
+    objects: Objects,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl LocalObjectServer {
+    pub fn start() -> Result<LocalObjectServer, PageError> {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let objects: Objects = Arc::new(Mutex::new(BTreeMap::new()));
+
+        let shared = objects.clone();
+        let svc = make_service_fn(move |_| {
+            let local = shared.clone();
+            async {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    LocalObjectServer::route(req, local.clone())
+                }))
+            }
+        });
+        let _guard = runtime.enter();
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .map_err(|_| IoError(ErrorKind::AddrNotAvailable))?
+            .serve(svc);
+        let addr = server.local_addr();
+        let (shutdown, signal) = oneshot::channel::<()>();
+        runtime.spawn(server.with_graceful_shutdown(async {
+            let _ = signal.await;
+        }));
+
+        Ok(LocalObjectServer {
+            runtime: Some(runtime),
+            addr,
+            objects,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // Every stored key, prefixed with its bucket.
+    pub fn keys(&self) -> Vec<String> {
+        self.objects.lock().unwrap().keys().cloned().collect()
+    }
+
+    // Flips a bit of a stored object without updating its checksum.
+    pub fn corrupt(&self, key: &str) {
+        if let Some(object) = self.objects.lock().unwrap().get_mut(key) {
+            if let Some(byte) = object.data.first_mut() {
+                *byte ^= 0xff;
+            }
+        }
+    }
+
+    async fn route(req: Request<Body>, objects: Objects) -> Result<Response<Body>, Infallible> {
+        let path = req.uri().path().trim_start_matches('/').to_string();
+        let query = req.uri().query().unwrap_or("").to_string();
+        let mut response = Response::new(Body::empty());
+
+        match (req.method().clone(), path.find('/')) {
+            (Method::GET, None) => {
+                let params: BTreeMap<String, String> =
+                    url::form_urlencoded::parse(query.as_bytes())
+                        .into_owned()
+                        .collect();
+                if params.get("list-type").map(String::as_str) == Some("2") {
+                    let prefix = params.get("prefix").cloned().unwrap_or_default();
+                    *response.body_mut() =
+                        Body::from(LocalObjectServer::list(&objects, &path, &prefix));
+                } else {
+                    *response.status_mut() = StatusCode::BAD_REQUEST;
+                }
+            }
+            (Method::GET, Some(_)) => match objects.lock().unwrap().get(&path) {
+                Some(object) => {
+                    if let Some(checksum) = &object.checksum {
+                        response
+                            .headers_mut()
+                            .insert(CHECKSUM_HEADER, checksum.parse().unwrap());
+                    }
+                    *response.body_mut() = Body::from(object.data.clone());
+                }
+                None => *response.status_mut() = StatusCode::NOT_FOUND,
+            },
+            (Method::PUT, Some(_)) => {
+                let checksum = req
+                    .headers()
+                    .get(CHECKSUM_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(String::from);
+                match hyper::body::to_bytes(req.into_body()).await {
+                    Ok(data) => {
+                        let object = Object {
+                            data: data.to_vec(),
+                            checksum,
+                        };
+                        objects.lock().unwrap().insert(path, object);
+                    }
+                    Err(_) => *response.status_mut() = StatusCode::BAD_REQUEST,
+                }
+            }
+            // Like S3, deleting a missing object succeeds.
+            (Method::DELETE, Some(_)) => {
+                objects.lock().unwrap().remove(&path);
+                *response.status_mut() = StatusCode::NO_CONTENT;
+            }
+            _ => *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED,
+        }
+        Ok(response)
+    }
+
+    fn list(objects: &Objects, bucket: &str, prefix: &str) -> String {
+        let bucket_prefix = format!("{}/", bucket);
+        let mut body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ListBucketResult><Name>{}</Name><Prefix>{}</Prefix>\
+             <IsTruncated>false</IsTruncated>",
+            bucket, prefix
+        );
+        for (key, object) in objects.lock().unwrap().iter() {
+            if let Some(key) = key.strip_prefix(&bucket_prefix) {
+                if key.starts_with(prefix) {
+                    body.push_str(&format!(
+                        "<Contents><Key>{}</Key><Size>{}</Size></Contents>",
+                        key,
+                        object.data.len()
+                    ));
+                }
+            }
+        }
+        body.push_str("</ListBucketResult>");
+        body
+    }
+}
+
+impl Drop for LocalObjectServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
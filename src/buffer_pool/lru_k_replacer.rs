@@ -0,0 +1,279 @@
+use crate::buffer_pool::{FrameId, PageId, Replacer, ReplacerRep};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+struct FrameHistory {
+    // The times of the last K uncorrelated accesses, most recent first.
+    history: VecDeque<u64>,
+    last_access: u64,
+    evictable: bool,
+    // Chosen as a victim, so the next access is to the page that replaces it.
+    evicted: bool,
+}
+
+// LRU-K (O'Neil et al.) evicts the unpinned frame whose K-th most recent access is the oldest,
+// its backward K-distance being the largest. Frames accessed fewer than K times have an infinite
+// distance and go first, least recently used first, which keeps a single scan from pushing out
+// pages that are used over and over.
+//
+// Accesses within `correlated_period` of the previous access of a frame, e.g. a read followed by
+// an update of the same page, count as one. Frames still inside that period are only evicted
+// when nothing else can be. Time is counted in accesses.
+pub struct LruKReplacer {
+    k: usize,
+    correlated_period: u64,
+    now: u64,
+    frames: BTreeMap<FrameId, FrameHistory>,
+}
+
+impl LruKReplacer {
+    pub fn new(k: usize, correlated_period: u64) -> LruKReplacer {
+        assert!(k > 0, "K must be at least 1");
+        LruKReplacer {
+            k,
+            correlated_period,
+            now: 0,
+            frames: BTreeMap::new(),
+        }
+    }
+
+    fn frame(&mut self, id: FrameId) -> &mut FrameHistory {
+        self.frames.entry(id).or_insert_with(|| FrameHistory {
+            history: VecDeque::new(),
+            last_access: 0,
+            evictable: false,
+            evicted: false,
+        })
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn victim(&mut self) -> Option<FrameId> {
+        // The frame is needed for the next access.
+        let now = self.now + 1;
+        let correlated_period = self.correlated_period;
+        let k = self.k;
+        let candidates = || self.frames.iter().filter(|(_, frame)| frame.evictable);
+        let uncorrelated = candidates()
+            .filter(|(_, frame)| now - frame.last_access > correlated_period)
+            .count();
+        let id = candidates()
+            .filter(|(_, frame)| uncorrelated == 0 || now - frame.last_access > correlated_period)
+            .min_by_key(|(_, frame)| {
+                let kth_access = frame.history.get(k - 1).copied();
+                (kth_access.is_some(), kth_access, frame.last_access)
+            })
+            .map(|(id, _)| *id)?;
+        // The history is kept until a new page is read into the frame, in case the old page
+        // can't be written back and stays.
+        let frame = self.frame(id);
+        frame.evictable = false;
+        frame.evicted = true;
+        Some(id)
+    }
+
+    fn unpin(&mut self, id: FrameId) {
+        let frame = self.frame(id);
+        frame.evictable = true;
+        frame.evicted = false;
+    }
+
+    fn pin(&mut self, id: FrameId) {
+        if let Some(frame) = self.frames.get_mut(&id) {
+            frame.evictable = false;
+        }
+    }
+
+    // The accesses of a deleted page don't count for the next page in its frame.
+    fn remove(&mut self, id: FrameId) {
+        self.frames.remove(&id);
+    }
+
+    fn record_access(&mut self, id: FrameId, _page_id: PageId) {
+        self.now += 1;
+        let now = self.now;
+        let k = self.k;
+        let correlated_period = self.correlated_period;
+        let frame = self.frame(id);
+        if frame.evicted {
+            frame.history.clear();
+            frame.evicted = false;
+        }
+        if frame.history.is_empty() {
+            frame.history.push_front(now);
+        } else if now - frame.last_access > correlated_period {
+            // The correlated accesses since the last uncorrelated one collapse into a single
+            // point in time, so the older accesses move forward by the length of that period.
+            let correlated = frame.last_access - frame.history[0];
+            for access in frame.history.iter_mut() {
+                *access += correlated;
+            }
+            frame.history.push_front(now);
+            frame.history.truncate(k);
+        }
+        frame.last_access = now;
+    }
+
+    fn response(&self) -> ReplacerRep {
+        let frames = self
+            .frames
+            .iter()
+            .map(|(id, frame)| LruKFrame {
+                frame: *id,
+                history: frame.history.iter().copied().collect(),
+                last_access: frame.last_access,
+                evictable: frame.evictable,
+            })
+            .collect();
+        let rep = LruKReplacerRep {
+            k: self.k,
+            correlated_period: self.correlated_period,
+            now: self.now,
+            frames,
+        };
+        ReplacerRep::new("LruKReplacer", &rep)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LruKReplacerRep {
+    #[serde(rename = "K")]
+    pub k: usize,
+    #[serde(rename = "CorrelatedPeriod")]
+    pub correlated_period: u64,
+    #[serde(rename = "Now")]
+    pub now: u64,
+    #[serde(rename = "Frames")]
+    pub frames: Vec<LruKFrame>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LruKFrame {
+    #[serde(rename = "Frame")]
+    pub frame: FrameId,
+    // Most recent first.
+    #[serde(rename = "History")]
+    pub history: Vec<u64>,
+    #[serde(rename = "LastAccess")]
+    pub last_access: u64,
+    #[serde(rename = "Evictable")]
+    pub evictable: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManagerMock, LruKReplacer, LruKReplacerRep, Replacer, MAX_POOL_SIZE,
+    };
+
+    // Frame `i` holds page `i`.
+    fn access(r: &mut LruKReplacer, ids: &[i32]) {
+        for id in ids {
+            r.record_access(*id, *id);
+        }
+    }
+
+    #[test]
+    fn lru_k_replacer() {
+        let mut r = LruKReplacer::new(2, 0);
+        access(&mut r, &[1, 2, 3, 4, 1, 2, 5, 1]);
+        for id in 1..=5 {
+            r.unpin(id);
+        }
+        r.pin(4);
+
+        // 3 and 5 were accessed only once, 3 least recently.
+        assert_eq!(Some(3), r.victim());
+        assert_eq!(Some(5), r.victim());
+        // The second most recent access of 2 is older than that of 1.
+        assert_eq!(Some(2), r.victim());
+        assert_eq!(Some(1), r.victim());
+        assert_eq!(None, r.victim());
+
+        r.unpin(4);
+        assert_eq!(Some(4), r.victim());
+    }
+
+    #[test]
+    fn removed_frame() {
+        let mut r = LruKReplacer::new(2, 0);
+        access(&mut r, &[2, 2, 1, 1]);
+        // The page in frame 1 was deleted and a new page took over the frame.
+        r.remove(1);
+        access(&mut r, &[1]);
+        r.unpin(1);
+        r.unpin(2);
+
+        assert_eq!(Some(1), r.victim());
+    }
+
+    #[test]
+    fn failed_eviction() {
+        let mut r = LruKReplacer::new(2, 0);
+        access(&mut r, &[1, 1, 2, 2]);
+        r.unpin(1);
+        r.unpin(2);
+        assert_eq!(Some(1), r.victim());
+        // Writing back the page failed, so it stays in its frame with its history.
+        r.unpin(1);
+        access(&mut r, &[3]);
+        r.unpin(3);
+
+        assert_eq!(Some(3), r.victim());
+        // Page 4 replaces page 3.
+        r.record_access(3, 4);
+        r.unpin(3);
+        assert_eq!(Some(3), r.victim());
+        assert_eq!(Some(1), r.victim());
+    }
+
+    #[test]
+    fn correlated_period() {
+        let mut r = LruKReplacer::new(2, 2);
+        access(&mut r, &[1, 1, 2, 3, 2]);
+        let rep: LruKReplacerRep = r.response().state("LruKReplacer").unwrap();
+        assert_eq!(5, rep.now);
+        // The second accesses of 1 and 2 were correlated with their first ones.
+        assert_eq!(vec![1], rep.frames[0].history);
+        assert_eq!(2, rep.frames[0].last_access);
+        assert_eq!(vec![3], rep.frames[1].history);
+        assert_eq!(5, rep.frames[1].last_access);
+        r.unpin(1);
+        r.unpin(2);
+        r.unpin(3);
+
+        // 2 and 3 were accessed too recently to be evicted while 1 can be.
+        assert_eq!(Some(1), r.victim());
+        assert_eq!(Some(3), r.victim());
+        assert_eq!(Some(2), r.victim());
+
+        // A later uncorrelated access shifts the history by the correlated period.
+        access(&mut r, &[5, 5, 5]);
+        access(&mut r, &[6, 6, 6, 5]);
+        let rep: LruKReplacerRep = r.response().state("LruKReplacer").unwrap();
+        let frame = rep.frames.iter().find(|frame| frame.frame == 5).unwrap();
+        assert_eq!(vec![12, 8], frame.history);
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let replacer = Box::new(LruKReplacer::new(2, 0));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(id, false).unwrap();
+        }
+        // Page 1 is hot, the other pages were only created.
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+
+        assert_eq!(5, bpm.new_page().unwrap().id());
+        assert!(!bpm.page_table.contains_key(&2));
+        bpm.unpin_page(5, false).unwrap();
+        bpm.fetch_page(2).unwrap();
+        assert!(!bpm.page_table.contains_key(&3));
+        assert!(bpm.page_table.contains_key(&1));
+    }
+}
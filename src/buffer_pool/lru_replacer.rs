@@ -0,0 +1,170 @@
+use crate::buffer_pool::{FrameId, Replacer, ReplacerRep};
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone)]
+struct Link {
+    prev: Option<usize>,
+    next: Option<usize>,
+    linked: bool,
+}
+
+const UNLINKED: Link = Link {
+    prev: None,
+    next: None,
+    linked: false,
+};
+
+// Evicts the frame that was unpinned the longest time ago. Unpinned frames form a doubly linked
+// list through `links`, indexed by frame id, from the least to the most recently unpinned, so
+// every operation takes constant time.
+#[derive(Default)]
+pub struct LruReplacer {
+    links: Vec<Link>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+impl LruReplacer {
+    pub fn new() -> LruReplacer {
+        LruReplacer {
+            links: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+
+    fn is_linked(&self, frame: usize) -> bool {
+        self.links.get(frame).is_some_and(|link| link.linked)
+    }
+
+    fn push_back(&mut self, frame: usize) {
+        if frame >= self.links.len() {
+            self.links.resize(frame + 1, UNLINKED);
+        }
+        self.links[frame] = Link {
+            prev: self.tail,
+            next: None,
+            linked: true,
+        };
+        match self.tail {
+            Some(tail) => self.links[tail].next = Some(frame),
+            None => self.head = Some(frame),
+        }
+        self.tail = Some(frame);
+    }
+
+    fn unlink(&mut self, frame: usize) {
+        let Link { prev, next, .. } = self.links[frame];
+        match prev {
+            Some(prev) => self.links[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.links[next].prev = prev,
+            None => self.tail = prev,
+        }
+        self.links[frame] = UNLINKED;
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn victim(&mut self) -> Option<FrameId> {
+        let frame = self.head?;
+        self.unlink(frame);
+        Some(frame as FrameId)
+    }
+
+    // Unpinning a frame that is already unpinned doesn't make it more recent.
+    fn unpin(&mut self, id: FrameId) {
+        if !self.is_linked(id as usize) {
+            self.push_back(id as usize);
+        }
+    }
+
+    fn pin(&mut self, id: FrameId) {
+        if self.is_linked(id as usize) {
+            self.unlink(id as usize);
+        }
+    }
+
+    fn response(&self) -> ReplacerRep {
+        let mut frames = Vec::new();
+        let mut frame = self.head;
+        while let Some(id) = frame {
+            frames.push(id as FrameId);
+            frame = self.links[id].next;
+        }
+        ReplacerRep::new("LruReplacer", &LruReplacerRep { frames })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LruReplacerRep {
+    // Least recently unpinned first.
+    #[serde(rename = "Frames")]
+    pub frames: Vec<FrameId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManagerMock, LruReplacer, LruReplacerRep, Replacer, MAX_POOL_SIZE,
+    };
+
+    fn frames(r: &LruReplacer) -> Vec<i32> {
+        let rep: LruReplacerRep = r.response().state("LruReplacer").unwrap();
+        rep.frames
+    }
+
+    #[test]
+    fn lru_replacer() {
+        let mut r = LruReplacer::new();
+        r.unpin(1);
+        r.unpin(2);
+        r.unpin(3);
+        r.unpin(4);
+        r.unpin(5);
+        r.unpin(6);
+        r.unpin(1);
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], frames(&r));
+        assert_eq!(Some(1), r.victim());
+        assert_eq!(Some(2), r.victim());
+        assert_eq!(Some(3), r.victim());
+
+        r.pin(3);
+        r.pin(4);
+        r.pin(6);
+        assert_eq!(vec![5], frames(&r));
+
+        r.unpin(4);
+        r.unpin(6);
+        assert_eq!(Some(5), r.victim());
+        assert_eq!(Some(4), r.victim());
+        assert_eq!(Some(6), r.victim());
+        assert_eq!(None, r.victim());
+
+        r.unpin(0);
+        assert_eq!(vec![0], frames(&r));
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let mut bpm =
+            BufferPoolManager::with_replacer(DiskManagerMock::new(), Box::new(LruReplacer::new()));
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in [2, 1, 4, 3].iter() {
+            bpm.unpin_page(*id, false).unwrap();
+        }
+        // Fetching page 1 pins its frame again, so it is unpinned last.
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+
+        assert_eq!(5, bpm.new_page().unwrap().id());
+        assert!(bpm.fetch_page(2).is_ok());
+        let rep: LruReplacerRep = bpm.response().replacer.state("LruReplacer").unwrap();
+        assert_eq!(vec![2, 0], rep.frames);
+    }
+}
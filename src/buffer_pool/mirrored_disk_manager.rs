@@ -0,0 +1,281 @@
+use crate::buffer_pool::PageError::{Corrupted, IoError};
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId};
+use std::any::Any;
+use std::io::ErrorKind;
+
+// Keeps an identical copy of every page on each of its mirrors. Reads go to the primary mirror
+// first, and when a copy is corrupted or can't be read the page is read from the next mirror and
+// the bad copies are rewritten.
+//
+// Mirrors must hand out the same page ids, so they should start out empty or as copies.
+pub struct MirroredDiskManager {
+    mirrors: Vec<Box<dyn DiskManager + Send>>,
+    primary: usize,
+    repairs: u64,
+}
+
+impl MirroredDiskManager {
+    pub fn new(mirrors: Vec<Box<dyn DiskManager + Send>>) -> Box<MirroredDiskManager> {
+        assert!(!mirrors.is_empty(), "at least one mirror is required");
+        Box::new(MirroredDiskManager {
+            mirrors,
+            primary: 0,
+            repairs: 0,
+        })
+    }
+
+    pub fn mirror(&self, index: usize) -> &dyn DiskManager {
+        self.mirrors[index].as_ref()
+    }
+
+    // Chooses which mirror is read first, e.g. the one on the fastest device.
+    pub fn set_primary(&mut self, index: usize) {
+        assert!(index < self.mirrors.len(), "no such mirror");
+        self.primary = index;
+    }
+
+    // The number of bad copies rewritten so far.
+    pub fn repairs(&self) -> u64 {
+        self.repairs
+    }
+
+    // Mirror indexes in read order, starting with the primary.
+    fn read_order(&self) -> impl Iterator<Item = usize> {
+        let primary = self.primary;
+        let len = self.mirrors.len();
+        (0..len).map(move |i| (primary + i) % len)
+    }
+
+    // Applies an operation to every mirror, even after one fails, and returns the first error.
+    fn for_each_mirror(
+        &mut self,
+        mut operation: impl FnMut(&mut dyn DiskManager) -> Result<(), PageError>,
+    ) -> Result<(), PageError> {
+        let mut result = Ok(());
+        for mirror in self.mirrors.iter_mut() {
+            if let Err(e) = operation(mirror.as_mut()) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+impl DiskManager for MirroredDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        let mut bad_copies: Vec<usize> = Vec::new();
+        let mut first_error = None;
+        for index in self.read_order().collect::<Vec<_>>() {
+            match self.mirrors[index].read_page(id, page) {
+                Ok(()) => {
+                    for bad in bad_copies {
+                        // A failed repair is retried on the next read.
+                        if self.mirrors[bad].write_page(page).is_ok() {
+                            self.repairs += 1;
+                        }
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    if matches!(e, Corrupted { .. } | IoError(_)) {
+                        bad_copies.push(index);
+                    }
+                    first_error = first_error.or(Some(e));
+                }
+            }
+        }
+        Err(first_error.unwrap())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        self.for_each_mirror(|mirror| mirror.write_page(page))
+    }
+
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        self.for_each_mirror(|mirror| mirror.write_pages(pages))
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.for_each_mirror(|mirror| mirror.sync())
+    }
+
+    // Every mirror has to allocate the same id. If one doesn't, the allocation is undone.
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let mut ids = Vec::with_capacity(self.mirrors.len());
+        let mut result = Ok(());
+        for mirror in self.mirrors.iter_mut() {
+            match mirror.allocate_page() {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if result.is_ok() && ids.iter().any(|id| *id != ids[0]) {
+            result = Err(IoError(ErrorKind::InvalidData));
+        }
+        match result {
+            Ok(()) => Ok(ids[0]),
+            Err(e) => {
+                for (mirror, id) in self.mirrors.iter_mut().zip(ids) {
+                    let _ = mirror.deallocate_page(id);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        self.for_each_mirror(|mirror| mirror.deallocate_page(id))
+    }
+
+    // Identical mirrors move the same pages, anything else means they have diverged. Mirrors
+    // holding different pages are refused before any of them is compacted.
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        let pages = self.mirrors[0].pages_on_disk();
+        if self
+            .mirrors
+            .iter()
+            .any(|mirror| mirror.pages_on_disk() != pages)
+        {
+            return Err(IoError(ErrorKind::InvalidData));
+        }
+        let mut moves = Vec::new();
+        for (index, mirror) in self.mirrors.iter_mut().enumerate() {
+            let mirror_moves = mirror.compact()?;
+            if index == 0 {
+                moves = mirror_moves;
+            } else if mirror_moves != moves {
+                return Err(IoError(ErrorKind::InvalidData));
+            }
+        }
+        Ok(moves)
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.mirrors[self.primary].pages_on_disk()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::{IoError, PageNotFound};
+    use crate::buffer_pool::tests::{corrupt_page, data_file, page, read};
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, DiskManagerMock, FaultyDiskManager, FileDiskManager,
+        MirroredDiskManager, Operation, Page,
+    };
+    use std::fs;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn repair_corrupted_copy() {
+        let first = data_file("mirrored_first");
+        let second = data_file("mirrored_second");
+        let mut dm = MirroredDiskManager::new(vec![
+            FileDiskManager::open(&first).unwrap(),
+            FileDiskManager::open(&second).unwrap(),
+        ]);
+        for _ in 0..2 {
+            dm.allocate_page().unwrap();
+        }
+        dm.write_pages(&[&page(1, b"11111111"), &page(2, b"22222222")])
+            .unwrap();
+
+        corrupt_page(&first, 2);
+        assert_eq!(*b"22222222", read(&mut dm, 2).unwrap().data);
+        assert_eq!(1, dm.repairs());
+        let mut primary = FileDiskManager::open(&first).unwrap();
+        assert_eq!(*b"22222222", read(&mut primary, 2).unwrap().data);
+
+        // With the primary switched, the second copy is read and repaired first.
+        corrupt_page(&second, 1);
+        dm.set_primary(1);
+        assert_eq!(*b"11111111", read(&mut dm, 1).unwrap().data);
+        assert_eq!(2, dm.repairs());
+
+        corrupt_page(&first, 1);
+        corrupt_page(&second, 1);
+        assert!(read(&mut dm, 1).is_err());
+        assert_eq!(PageNotFound, read(&mut dm, 3).unwrap_err());
+    }
+
+    #[test]
+    fn failing_mirror() {
+        let mut faulty = FaultyDiskManager::new(DiskManagerMock::new());
+        faulty.fail(Operation::Read).times(1);
+        faulty.fail(Operation::Write).on_page(2);
+        let mut dm = MirroredDiskManager::new(vec![faulty, DiskManagerMock::new()]);
+
+        for id in 1..=2 {
+            assert_eq!(id, dm.allocate_page().unwrap());
+        }
+        dm.write_page(&page(1, b"11111111")).unwrap();
+        assert_eq!(*b"11111111", read(&mut dm, 1).unwrap().data);
+        assert_eq!(1, dm.repairs());
+
+        // The healthy mirror still gets the write.
+        assert_eq!(
+            IoError(ErrorKind::Other),
+            dm.write_page(&page(2, b"22222222")).unwrap_err()
+        );
+        assert_eq!(*b"22222222", read(dm.mirrors[1].as_mut(), 2).unwrap().data);
+    }
+
+    #[test]
+    fn diverged_allocation() {
+        let mut second = DiskManagerMock::new();
+        second.allocate_page().unwrap();
+        let mut dm = MirroredDiskManager::new(vec![DiskManagerMock::new(), second]);
+
+        assert_eq!(
+            IoError(ErrorKind::InvalidData),
+            dm.allocate_page().unwrap_err()
+        );
+        assert_eq!(PageNotFound, dm.mirrors[0].deallocate_page(1).unwrap_err());
+    }
+
+    #[test]
+    fn diverged_compaction() {
+        let mut dm = MirroredDiskManager::new(vec![DiskManagerMock::new(), DiskManagerMock::new()]);
+        for (index, mirror) in dm.mirrors.iter_mut().enumerate() {
+            for _ in 0..2 {
+                let id = mirror.allocate_page().unwrap();
+                mirror.write_page(&Page::new(id)).unwrap();
+            }
+            mirror.deallocate_page(index as i32 + 1).unwrap();
+        }
+
+        assert_eq!(IoError(ErrorKind::InvalidData), dm.compact().unwrap_err());
+        assert_eq!(vec![2], dm.mirrors[0].pages_on_disk());
+        assert_eq!(vec![1], dm.mirrors[1].pages_on_disk());
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let first = data_file("mirrored_bpm_first");
+        let second = data_file("mirrored_bpm_second");
+        let open = || {
+            MirroredDiskManager::new(vec![
+                FileDiskManager::open(&first).unwrap(),
+                FileDiskManager::open(&second).unwrap(),
+            ])
+        };
+        {
+            let mut bpm = BufferPoolManager::new(open());
+            bpm.new_page().unwrap().data = *b"mirrored";
+            bpm.flush_all_pages().unwrap();
+        }
+        fs::remove_file(&first).unwrap();
+        fs::copy(&second, &first).unwrap();
+        corrupt_page(&first, 1);
+
+        let mut bpm = BufferPoolManager::new(open());
+        assert_eq!(*b"mirrored", bpm.fetch_page(1).unwrap().data);
+    }
+}
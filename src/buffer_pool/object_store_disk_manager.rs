@@ -0,0 +1,321 @@
+use crate::buffer_pool::PageError::{Corrupted, IoError, OutOfStorage, PageNotFound};
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, MAX_NUM_DISK_PAGES, PAGE_SIZE};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, HeaderMap, Method, Request, StatusCode};
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::runtime::{self, Runtime};
+
+pub(crate) const CHECKSUM_HEADER: &str = "x-amz-meta-crc32c";
+const KEY_PREFIX: &str = "pages/";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn page_key(id: PageId) -> String {
+    format!("{}{:08}", KEY_PREFIX, id)
+}
+
+struct ObjectResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+// Stores every page as its own object, `pages/<page id>`, in a bucket of an S3 compatible object
+// store. Only plain unauthenticated GET, PUT, DELETE and LIST (v2) requests are used.
+//
+// Requests run on a runtime owned by the disk manager and the caller blocks on a channel for the
+// result, so it can be used both from plain threads and from within the server's runtime. Every
+// request, connecting included, has to finish within the timeout so a stalled store fails the
+// call with `TimedOut` instead of hanging the pool.
+pub struct ObjectStoreDiskManager {
+    runtime: Option<Runtime>,
+    client: Client<HttpConnector>,
+    timeout: Duration,
+    bucket_url: String,
+    allocated: BTreeSet<PageId>,
+}
+
+impl ObjectStoreDiskManager {
+    // `endpoint` is the base URL of the object store, e.g. `http://127.0.0.1:9000`.
+    pub fn open(endpoint: &str, bucket: &str) -> Result<Box<ObjectStoreDiskManager>, PageError> {
+        ObjectStoreDiskManager::open_with_timeout(endpoint, bucket, DEFAULT_TIMEOUT)
+    }
+
+    pub fn open_with_timeout(
+        endpoint: &str,
+        bucket: &str,
+        timeout: Duration,
+    ) -> Result<Box<ObjectStoreDiskManager>, PageError> {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(timeout));
+        let mut manager = Box::new(ObjectStoreDiskManager {
+            runtime: Some(runtime),
+            client: Client::builder().build(connector),
+            timeout,
+            bucket_url: format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+            allocated: BTreeSet::new(),
+        });
+        manager.allocated = manager.list()?.into_iter().collect();
+        Ok(manager)
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        uri: String,
+        body: Vec<u8>,
+        checksum: Option<u32>,
+    ) -> Result<ObjectResponse, PageError> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(checksum) = checksum {
+            builder = builder.header(CHECKSUM_HEADER, checksum.to_string());
+        }
+        let request = builder
+            .body(Body::from(body))
+            .map_err(|_| IoError(ErrorKind::InvalidInput))?;
+
+        let client = self.client.clone();
+        let timeout = self.timeout;
+        let (sender, receiver) = mpsc::channel();
+        self.runtime.as_ref().unwrap().spawn(async move {
+            let result = tokio::time::timeout(timeout, async {
+                let response = client.request(request).await?;
+                let status = response.status();
+                let headers = response.headers().clone();
+                let body = hyper::body::to_bytes(response.into_body()).await?;
+                Ok::<_, hyper::Error>(ObjectResponse {
+                    status,
+                    headers,
+                    body: body.to_vec(),
+                })
+            })
+            .await;
+            let _ = sender.send(result);
+        });
+        match receiver.recv() {
+            Ok(Ok(Ok(response))) if response.status == StatusCode::NOT_FOUND => Err(PageNotFound),
+            Ok(Ok(Ok(response))) if !response.status.is_success() => Err(IoError(ErrorKind::Other)),
+            Ok(Ok(Ok(response))) => Ok(response),
+            Ok(Ok(Err(e))) if e.is_timeout() => Err(IoError(ErrorKind::TimedOut)),
+            Ok(Ok(Err(e))) if e.is_connect() => Err(IoError(ErrorKind::ConnectionRefused)),
+            Ok(Err(_)) => Err(IoError(ErrorKind::TimedOut)),
+            Ok(Ok(Err(_))) | Err(_) => Err(IoError(ErrorKind::Other)),
+        }
+    }
+
+    fn object_url(&self, id: PageId) -> String {
+        format!("{}/{}", self.bucket_url, page_key(id))
+    }
+
+    fn put(&self, page: &Page) -> Result<(), PageError> {
+        self.request(
+            Method::PUT,
+            self.object_url(page.id),
+            page.data.to_vec(),
+            Some(page.checksum()),
+        )?;
+        Ok(())
+    }
+
+    // A single LIST response holds up to 1000 keys, far more than `MAX_NUM_DISK_PAGES`.
+    fn list(&self) -> Result<Vec<PageId>, PageError> {
+        let uri = format!("{}?list-type=2&prefix={}", self.bucket_url, KEY_PREFIX);
+        let response = self.request(Method::GET, uri, Vec::new(), None)?;
+        let body = String::from_utf8_lossy(&response.body);
+        let mut ids = Vec::new();
+        for key in body.split("<Key>").skip(1) {
+            let key = key.split("</Key>").next().unwrap_or("");
+            if let Some(Ok(id)) = key.strip_prefix(KEY_PREFIX).map(str::parse) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+}
+
+impl Drop for ObjectStoreDiskManager {
+    // Dropping a runtime blocks on its worker threads, which isn't allowed inside another runtime.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl DiskManager for ObjectStoreDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        if !self.allocated.contains(&id) {
+            return Err(PageNotFound);
+        }
+        let response = self.request(Method::GET, self.object_url(id), Vec::new(), None)?;
+        if response.body.len() != PAGE_SIZE {
+            return Err(Corrupted { page_id: id });
+        }
+        page.id = id;
+        page.data.copy_from_slice(&response.body);
+        let checksum = response
+            .headers
+            .get(CHECKSUM_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u32>().ok());
+        if checksum != Some(page.checksum()) {
+            return Err(Corrupted { page_id: id });
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        if !self.allocated.contains(&page.id) {
+            return Err(PageNotFound);
+        }
+        self.put(page)
+    }
+
+    // Objects are durable once the store acknowledges the PUT.
+    fn sync(&mut self) -> Result<(), PageError> {
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = (1..=MAX_NUM_DISK_PAGES)
+            .find(|id| !self.allocated.contains(id))
+            .ok_or(OutOfStorage)?;
+        self.put(&Page::new(id))?;
+        self.allocated.insert(id);
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if !self.allocated.contains(&id) {
+            return Err(PageNotFound);
+        }
+        self.request(Method::DELETE, self.object_url(id), Vec::new(), None)?;
+        self.allocated.remove(&id);
+        Ok(())
+    }
+
+    // Falls back to the pages this disk manager knows about when the store can't be listed.
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.list()
+            .unwrap_or_else(|_| self.allocated.iter().copied().collect())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::local_object_server::LocalObjectServer;
+    use crate::buffer_pool::object_store_disk_manager::page_key;
+    use crate::buffer_pool::page::PageError::{Corrupted, IoError, OutOfStorage, PageNotFound};
+    use crate::buffer_pool::tests::read;
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, ObjectStoreDiskManager, Page, MAX_NUM_DISK_PAGES,
+    };
+    use std::io::ErrorKind;
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+    use tokio::net::TcpSocket;
+
+    #[test]
+    fn objects() {
+        let server = LocalObjectServer::start().unwrap();
+        {
+            let mut dm = ObjectStoreDiskManager::open(&server.endpoint(), "bucket").unwrap();
+            for i in 1..=3 {
+                assert_eq!(i, dm.allocate_page().unwrap());
+                let mut page = Page::new(i);
+                page.data = [i as u8; 8];
+                dm.write_page(&page).unwrap();
+            }
+            dm.deallocate_page(2).unwrap();
+            assert_eq!(vec![1, 3], dm.pages_on_disk());
+            assert_eq!(PageNotFound, read(&mut dm, 2).unwrap_err());
+            assert_eq!(PageNotFound, dm.deallocate_page(2).unwrap_err());
+            assert_eq!(PageNotFound, dm.write_page(&Page::new(4)).unwrap_err());
+        }
+        assert_eq!(
+            vec!["bucket/pages/00000001", "bucket/pages/00000003"],
+            server.keys()
+        );
+
+        let mut dm = ObjectStoreDiskManager::open(&server.endpoint(), "bucket").unwrap();
+        assert_eq!([1; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!([3; 8], read(&mut dm, 3).unwrap().data);
+        assert_eq!(2, dm.allocate_page().unwrap());
+        for _ in 3..MAX_NUM_DISK_PAGES {
+            dm.allocate_page().unwrap();
+        }
+        assert_eq!(OutOfStorage, dm.allocate_page().unwrap_err());
+
+        let other = ObjectStoreDiskManager::open(&server.endpoint(), "other").unwrap();
+        assert!(other.pages_on_disk().is_empty());
+    }
+
+    #[test]
+    fn detect_corrupted_object() {
+        let server = LocalObjectServer::start().unwrap();
+        let mut dm = ObjectStoreDiskManager::open(&server.endpoint(), "bucket").unwrap();
+        let id = dm.allocate_page().unwrap();
+
+        server.corrupt(&format!("bucket/{}", page_key(id)));
+        assert_eq!(Corrupted { page_id: id }, read(&mut dm, id).unwrap_err());
+    }
+
+    #[test]
+    fn unreachable_store() {
+        // A bound socket that never listens keeps the port from being reused while refusing
+        // connections.
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let endpoint = format!("http://{}", socket.local_addr().unwrap());
+        assert_eq!(
+            IoError(ErrorKind::ConnectionRefused),
+            ObjectStoreDiskManager::open(&endpoint, "bucket")
+                .err()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn stalled_store() {
+        // The listener's backlog accepts the connection, but nobody ever answers the request.
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        assert_eq!(
+            IoError(ErrorKind::TimedOut),
+            ObjectStoreDiskManager::open_with_timeout(
+                &endpoint,
+                "bucket",
+                Duration::from_millis(100)
+            )
+            .err()
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let server = LocalObjectServer::start().unwrap();
+        {
+            let dm = ObjectStoreDiskManager::open(&server.endpoint(), "bucket").unwrap();
+            let mut bpm = BufferPoolManager::new(dm);
+            bpm.new_page().unwrap().data = *b"objstore";
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let dm = ObjectStoreDiskManager::open(&server.endpoint(), "bucket").unwrap();
+        let mut bpm = BufferPoolManager::new(dm);
+        assert_eq!(*b"objstore", bpm.fetch_page(1).unwrap().data);
+    }
+}
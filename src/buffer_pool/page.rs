@@ -1,7 +1,13 @@
 use std::fmt::{Display, Formatter};
-use std::fmt;
+use std::{fmt, io};
 use crate::buffer_pool::{PageId, Page, PAGE_SIZE};
 
+// CRC32C of a page id followed by the page data. Takes a slice, so pages of data files with
+// another page size can be checked as well.
+pub(crate) fn checksum(id: PageId, data: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(&id.to_le_bytes()), data)
+}
+
 impl Page {
     pub fn new(id: PageId) -> Box<Page> {
 
@@ -13,10 +19,22 @@ impl Page {
         })
     }
 
+    // Turns the page into a new, pinned and zeroed page with the given id.
+    pub fn reset(&mut self, id: PageId) {
+        self.id = id;
+        self.pin_count = 1;
+        self.is_dirty = false;
+        self.data = [0; PAGE_SIZE];
+    }
+
     pub fn id(&self) -> PageId {
         self.id
     }
 
+    pub fn checksum(&self) -> u32 {
+        checksum(self.id, &self.data)
+    }
+
     pub fn dec_pin_count(&mut self) -> bool {
         if self.pin_count > 0 {
             self.pin_count -= 1;
@@ -31,6 +49,18 @@ pub enum PageError {
     PageStillInUse,
     PoolExhausted,
     OutOfStorage,
+    Corrupted { page_id: PageId },
+    PageSizeMismatch { expected: usize, found: usize },
+    UnsupportedVersion { found: u32 },
+    UnsupportedFeatures { flags: u64 },
+    VersionPruned { version: u64 },
+    IoError(io::ErrorKind),
+}
+
+impl From<io::Error> for PageError {
+    fn from(e: io::Error) -> Self {
+        PageError::IoError(e.kind())
+    }
 }
 
 impl Display for PageError {
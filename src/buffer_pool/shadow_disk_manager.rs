@@ -0,0 +1,356 @@
+use crate::buffer_pool::file_disk_manager::{decode_slot, encode_slot, SLOT_SIZE};
+use crate::buffer_pool::file_io::{BufferedIo, FileIo};
+use crate::buffer_pool::PageError::{Corrupted, OutOfStorage, PageNotFound};
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, MAX_NUM_DISK_PAGES, PAGE_SIZE};
+use std::any::Any;
+use std::path::Path;
+
+const MAGIC: [u8; 8] = *b"BPMSHDW\0";
+const CHECKSUM_SIZE: usize = 4;
+const UNMAPPED: u32 = u32::MAX;
+
+// Every page can have a committed and an uncommitted copy at the same time.
+const NUM_SLOTS: usize = 2 * MAX_NUM_DISK_PAGES as usize;
+const TABLE_SIZE: usize = MAX_NUM_DISK_PAGES as usize * 4;
+const ROOT_SIZE: usize = MAGIC.len() + 8 + TABLE_SIZE + CHECKSUM_SIZE;
+
+type Table = Vec<Option<usize>>;
+
+fn root_offset(generation: u64) -> u64 {
+    (generation % 2) * ROOT_SIZE as u64
+}
+
+fn slot_offset(slot: usize) -> u64 {
+    (2 * ROOT_SIZE + slot * SLOT_SIZE) as u64
+}
+
+// A root holds the generation it was committed in and the indirection table, the physical slot
+// of every page id, with a CRC32C of both. The two roots at the start of the file are written
+// alternately, so a commit never overwrites the root it replaces.
+fn encode_root(generation: u64, table: &[Option<usize>]) -> [u8; ROOT_SIZE] {
+    let mut root = [0; ROOT_SIZE];
+    root[..8].copy_from_slice(&MAGIC);
+    root[8..16].copy_from_slice(&generation.to_le_bytes());
+    for (index, slot) in table.iter().enumerate() {
+        let slot = slot.map_or(UNMAPPED, |slot| slot as u32);
+        root[16 + index * 4..20 + index * 4].copy_from_slice(&slot.to_le_bytes());
+    }
+    let checksum = crc32c::crc32c(&root[..ROOT_SIZE - CHECKSUM_SIZE]);
+    root[ROOT_SIZE - CHECKSUM_SIZE..].copy_from_slice(&checksum.to_le_bytes());
+    root
+}
+
+fn decode_root(root: &[u8]) -> Option<(u64, Table)> {
+    let mut checksum = [0; CHECKSUM_SIZE];
+    checksum.copy_from_slice(&root[ROOT_SIZE - CHECKSUM_SIZE..]);
+    if root[..8] != MAGIC
+        || u32::from_le_bytes(checksum) != crc32c::crc32c(&root[..ROOT_SIZE - CHECKSUM_SIZE])
+    {
+        return None;
+    }
+    let mut generation = [0; 8];
+    generation.copy_from_slice(&root[8..16]);
+    let mut table = Vec::with_capacity(MAX_NUM_DISK_PAGES as usize);
+    for entry in root[16..16 + TABLE_SIZE].chunks_exact(4) {
+        let mut slot = [0; 4];
+        slot.copy_from_slice(entry);
+        table.push(match u32::from_le_bytes(slot) {
+            UNMAPPED => None,
+            slot if (slot as usize) < NUM_SLOTS => Some(slot as usize),
+            _ => return None,
+        });
+    }
+    Some((u64::from_le_bytes(generation), table))
+}
+
+// A copy-on-write disk manager. Writes never touch a committed page, they go to a free slot
+// and only update the in-memory indirection table until `commit` writes that table to the
+// inactive root. Opening the file, e.g. after a crash, brings back the last committed state, so
+// every change between two commits is applied atomically.
+pub struct ShadowDiskManager {
+    io: Box<dyn FileIo>,
+    generation: u64,
+    committed: Table,
+    current: Table,
+}
+
+impl ShadowDiskManager {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<ShadowDiskManager>, PageError> {
+        let io: Box<dyn FileIo> = Box::new(BufferedIo::open(path.as_ref())?);
+        let len = io.len()?;
+        let mut manager = Box::new(ShadowDiskManager {
+            io,
+            generation: 0,
+            committed: vec![None; MAX_NUM_DISK_PAGES as usize],
+            current: vec![None; MAX_NUM_DISK_PAGES as usize],
+        });
+        // A new file starts out with both roots holding the empty generation 0.
+        if len == 0 {
+            let root = encode_root(0, &manager.committed);
+            manager.io.write_at(&[root, root].concat(), 0)?;
+            manager.io.sync()?;
+            return Ok(manager);
+        }
+
+        let mut roots = [0; 2 * ROOT_SIZE];
+        if len < roots.len() as u64 {
+            return Err(Corrupted { page_id: 0 });
+        }
+        manager.io.read_at(&mut roots, 0)?;
+        let (generation, table) = roots
+            .chunks_exact(ROOT_SIZE)
+            .filter_map(decode_root)
+            .max_by_key(|(generation, _)| *generation)
+            .ok_or(Corrupted { page_id: 0 })?;
+        manager.generation = generation;
+        manager.committed = table.clone();
+        manager.current = table;
+        Ok(manager)
+    }
+
+    // The number of commits the file has seen.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Makes every change since the last commit durable at once. The new pages are synced before
+    // the root that points to them is written.
+    pub fn commit(&mut self) -> Result<(), PageError> {
+        if self.current == self.committed {
+            return Ok(());
+        }
+        self.io.sync()?;
+        let generation = self.generation + 1;
+        self.io.write_at(
+            &encode_root(generation, &self.current),
+            root_offset(generation),
+        )?;
+        self.io.sync()?;
+        self.generation = generation;
+        self.committed = self.current.clone();
+        Ok(())
+    }
+
+    // Drops every change since the last commit.
+    pub fn rollback(&mut self) {
+        self.current = self.committed.clone();
+    }
+
+    fn mapped_slot(&self, id: PageId) -> Option<usize> {
+        if !(1..=MAX_NUM_DISK_PAGES).contains(&id) {
+            return None;
+        }
+        self.current[id as usize - 1]
+    }
+
+    // A slot referenced by neither table. One always exists since each table maps at most
+    // `MAX_NUM_DISK_PAGES` slots.
+    fn free_slot(&self) -> usize {
+        (0..NUM_SLOTS)
+            .find(|slot| {
+                !self.committed.contains(&Some(*slot)) && !self.current.contains(&Some(*slot))
+            })
+            .unwrap()
+    }
+
+    fn write_slot(&mut self, page: &Page) -> Result<(), PageError> {
+        let index = page.id as usize - 1;
+        // Pages already copied since the last commit are only visible to this disk manager.
+        let slot = match self.current[index] {
+            Some(slot) if self.committed[index] != Some(slot) => slot,
+            _ => self.free_slot(),
+        };
+        // Slots are laid out like those of a `FileDiskManager` data file.
+        let mut bytes = [0; SLOT_SIZE];
+        encode_slot(page, &mut bytes);
+        self.io.write_at(&bytes, slot_offset(slot))?;
+        self.current[index] = Some(slot);
+        Ok(())
+    }
+}
+
+impl DiskManager for ShadowDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        let slot = self.mapped_slot(id).ok_or(PageNotFound)?;
+        let mut bytes = [0; SLOT_SIZE];
+        self.io.read_at(&mut bytes, slot_offset(slot))?;
+        page.data = decode_slot(&bytes, 0, PAGE_SIZE, id)?.data;
+        page.id = id;
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        if self.mapped_slot(page.id).is_none() {
+            return Err(PageNotFound);
+        }
+        self.write_slot(page)
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.commit()
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = (1..=MAX_NUM_DISK_PAGES)
+            .find(|id| self.mapped_slot(*id).is_none())
+            .ok_or(OutOfStorage)?;
+        self.write_slot(&Page::new(id))?;
+        Ok(id)
+    }
+
+    // The slot stays in use until the deallocation is committed.
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if self.mapped_slot(id).is_none() {
+            return Err(PageNotFound);
+        }
+        self.current[id as usize - 1] = None;
+        Ok(())
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        (1..=MAX_NUM_DISK_PAGES)
+            .filter(|id| self.mapped_slot(*id).is_some())
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::{Corrupted, PageNotFound};
+    use crate::buffer_pool::shadow_disk_manager::{root_offset, ROOT_SIZE};
+    use crate::buffer_pool::tests::{data_file, page, read};
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, ShadowDiskManager, MAX_NUM_DISK_PAGES,
+    };
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    fn tear_root(path: &Path, generation: u64) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(
+            root_offset(generation) + ROOT_SIZE as u64 - 1,
+        ))
+        .unwrap();
+        file.write_all(&[0xff]).unwrap();
+    }
+
+    #[test]
+    fn commit_and_reopen() {
+        let path = data_file("shadow_commit");
+        {
+            let mut dm = ShadowDiskManager::open(&path).unwrap();
+            for id in 1..=2 {
+                assert_eq!(id, dm.allocate_page().unwrap());
+            }
+            dm.write_page(&page(1, b"11111111")).unwrap();
+            dm.write_page(&page(2, b"22222222")).unwrap();
+            dm.commit().unwrap();
+            assert_eq!(1, dm.generation());
+
+            // Uncommitted changes are visible to the disk manager itself, but not after a crash.
+            dm.write_page(&page(1, b"uncommit")).unwrap();
+            dm.deallocate_page(2).unwrap();
+            assert_eq!(*b"uncommit", read(&mut dm, 1).unwrap().data);
+            assert_eq!(PageNotFound, read(&mut dm, 2).unwrap_err());
+            assert_eq!(2, dm.allocate_page().unwrap());
+            assert_eq!([0; 8], read(&mut dm, 2).unwrap().data);
+        }
+
+        let mut dm = ShadowDiskManager::open(&path).unwrap();
+        assert_eq!(1, dm.generation());
+        assert_eq!(vec![1, 2], dm.pages_on_disk());
+        assert_eq!(*b"11111111", read(&mut dm, 1).unwrap().data);
+        assert_eq!(*b"22222222", read(&mut dm, 2).unwrap().data);
+
+        dm.write_page(&page(2, b"rollback")).unwrap();
+        dm.rollback();
+        assert_eq!(*b"22222222", read(&mut dm, 2).unwrap().data);
+    }
+
+    #[test]
+    fn reopen_new_file() {
+        let path = data_file("shadow_reopen_new");
+        ShadowDiskManager::open(&path).unwrap();
+
+        let mut dm = ShadowDiskManager::open(&path).unwrap();
+        assert_eq!(0, dm.generation());
+        assert!(dm.pages_on_disk().is_empty());
+        assert_eq!(1, dm.allocate_page().unwrap());
+        dm.commit().unwrap();
+
+        let dm = ShadowDiskManager::open(&path).unwrap();
+        assert_eq!(1, dm.generation());
+        assert_eq!(vec![1], dm.pages_on_disk());
+    }
+
+    #[test]
+    fn torn_root() {
+        let path = data_file("shadow_torn_root");
+        {
+            let mut dm = ShadowDiskManager::open(&path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_page(&page(1, b"first   ")).unwrap();
+            dm.commit().unwrap();
+            dm.write_page(&page(1, b"second  ")).unwrap();
+            dm.commit().unwrap();
+        }
+        tear_root(&path, 2);
+        {
+            let mut dm = ShadowDiskManager::open(&path).unwrap();
+            assert_eq!(1, dm.generation());
+            assert_eq!(*b"first   ", read(&mut dm, 1).unwrap().data);
+        }
+
+        tear_root(&path, 1);
+        assert_eq!(
+            Corrupted { page_id: 0 },
+            ShadowDiskManager::open(&path).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn rewrite_every_page() {
+        let path = data_file("shadow_rewrite");
+        let mut dm = ShadowDiskManager::open(&path).unwrap();
+        for _ in 0..MAX_NUM_DISK_PAGES {
+            dm.allocate_page().unwrap();
+        }
+        dm.commit().unwrap();
+        for round in 0..3u8 {
+            for id in 1..=MAX_NUM_DISK_PAGES {
+                dm.write_page(&page(id, &[round; 8])).unwrap();
+                dm.write_page(&page(id, &[round + 1; 8])).unwrap();
+            }
+            dm.commit().unwrap();
+        }
+
+        let mut dm = ShadowDiskManager::open(&path).unwrap();
+        for id in 1..=MAX_NUM_DISK_PAGES {
+            assert_eq!([3; 8], read(&mut dm, id).unwrap().data);
+        }
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let path = data_file("shadow_bpm");
+        {
+            let mut bpm = BufferPoolManager::new(ShadowDiskManager::open(&path).unwrap());
+            bpm.new_page().unwrap().data = *b"balance1";
+            bpm.new_page().unwrap().data = *b"balance2";
+            bpm.sync().unwrap();
+
+            bpm.fetch_page(1).unwrap().data = *b"partial1";
+            bpm.unpin_page(1, true).unwrap();
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let mut bpm = BufferPoolManager::new(ShadowDiskManager::open(&path).unwrap());
+        assert_eq!(*b"balance1", bpm.fetch_page(1).unwrap().data);
+        assert_eq!(*b"balance2", bpm.fetch_page(2).unwrap().data);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, PAGE_SIZE};
+use std::any::Any;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeviceProfile {
+    pub read_latency: Duration,
+    pub write_latency: Duration,
+    // Moving the head costs `seek_settle + seek_per_page * distance`, nothing when the head is
+    // already there. Zero for solid state devices.
+    pub seek_settle: Duration,
+    pub seek_per_page: Duration,
+    pub bytes_per_second: u64,
+}
+
+impl DeviceProfile {
+    // A 7200 RPM drive: half a rotation on average before the sector comes around.
+    pub fn hdd() -> DeviceProfile {
+        DeviceProfile {
+            read_latency: Duration::from_micros(4_170),
+            write_latency: Duration::from_micros(4_170),
+            seek_settle: Duration::from_micros(1_000),
+            seek_per_page: Duration::from_micros(500),
+            bytes_per_second: 150_000_000,
+        }
+    }
+
+    pub fn sata_ssd() -> DeviceProfile {
+        DeviceProfile {
+            read_latency: Duration::from_micros(90),
+            write_latency: Duration::from_micros(30),
+            seek_settle: Duration::ZERO,
+            seek_per_page: Duration::ZERO,
+            bytes_per_second: 550_000_000,
+        }
+    }
+
+    pub fn nvme() -> DeviceProfile {
+        DeviceProfile {
+            read_latency: Duration::from_micros(20),
+            write_latency: Duration::from_micros(10),
+            seek_settle: Duration::ZERO,
+            seek_per_page: Duration::ZERO,
+            bytes_per_second: 3_500_000_000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct OpStats {
+    pub count: u64,
+    pub time: Duration,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SimulatedStats {
+    pub reads: OpStats,
+    pub writes: OpStats,
+    pub allocations: OpStats,
+    pub deallocations: OpStats,
+}
+
+// Wraps another disk manager and charges virtual time for every successful operation according
+// to a device profile. Nothing actually sleeps, so runs are fast and fully deterministic.
+pub struct SimulatedDiskManager<D: DiskManager> {
+    inner: D,
+    profile: DeviceProfile,
+    head: PageId,
+    elapsed: Duration,
+    stats: SimulatedStats,
+}
+
+impl<D: DiskManager> SimulatedDiskManager<D> {
+    pub fn new(inner: D, profile: DeviceProfile) -> Box<SimulatedDiskManager<D>> {
+        Box::new(SimulatedDiskManager {
+            inner,
+            profile,
+            head: 0,
+            elapsed: Duration::ZERO,
+            stats: SimulatedStats::default(),
+        })
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn stats(&self) -> SimulatedStats {
+        self.stats
+    }
+
+    // The head only moves to `id` once the operation succeeded.
+    fn cost(&self, id: PageId, latency: Duration) -> Duration {
+        let distance = (id - self.head).unsigned_abs();
+        let seek = if distance == 0 {
+            Duration::ZERO
+        } else {
+            self.profile.seek_settle + self.profile.seek_per_page * distance
+        };
+        let transfer =
+            Duration::from_nanos(PAGE_SIZE as u64 * 1_000_000_000 / self.profile.bytes_per_second);
+        seek + latency + transfer
+    }
+
+    fn charge(elapsed: &mut Duration, stats: &mut OpStats, time: Duration) {
+        *elapsed += time;
+        stats.count += 1;
+        stats.time += time;
+    }
+}
+
+impl<D: DiskManager + 'static> DiskManager for SimulatedDiskManager<D> {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        let time = self.cost(id, self.profile.read_latency);
+        self.inner.read_page(id, page)?;
+        self.head = id;
+        SimulatedDiskManager::<D>::charge(&mut self.elapsed, &mut self.stats.reads, time);
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        let time = self.cost(page.id, self.profile.write_latency);
+        self.inner.write_page(page)?;
+        self.head = page.id;
+        SimulatedDiskManager::<D>::charge(&mut self.elapsed, &mut self.stats.writes, time);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.inner.sync()
+    }
+
+    // Allocating and deallocating is charged as a page write at the affected page.
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = self.inner.allocate_page()?;
+        let time = self.cost(id, self.profile.write_latency);
+        self.head = id;
+        SimulatedDiskManager::<D>::charge(&mut self.elapsed, &mut self.stats.allocations, time);
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        self.inner.deallocate_page(id)?;
+        let time = self.cost(id, self.profile.write_latency);
+        self.head = id;
+        SimulatedDiskManager::<D>::charge(&mut self.elapsed, &mut self.stats.deallocations, time);
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        self.inner.compact()
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        self.inner.pages_on_disk()
+    }
+
+    fn header_page(&self) -> Option<Page> {
+        self.inner.header_page()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::tests::{data_file, read};
+    use crate::buffer_pool::{
+        BufferPoolManager, DeviceProfile, DiskManager, DiskManagerMock, FileDiskManager, Page,
+        SimulatedDiskManager,
+    };
+    use std::time::Duration;
+
+    fn write_pages(ids: &[i32], profile: DeviceProfile) -> Duration {
+        let mut dm = SimulatedDiskManager::new(DiskManagerMock::new(), profile);
+        for _ in 0..10 {
+            dm.inner.allocate_page().unwrap();
+        }
+        for id in ids {
+            dm.write_page(&Page::new(*id)).unwrap();
+        }
+        assert_eq!(ids.len() as u64, dm.stats().writes.count);
+        dm.elapsed()
+    }
+
+    #[test]
+    fn hdd_seeks() {
+        let sequential = write_pages(&[1, 2, 3, 4, 5], DeviceProfile::hdd());
+        let random = write_pages(&[5, 1, 4, 2, 3], DeviceProfile::hdd());
+        assert!(sequential < random);
+
+        let profile = DeviceProfile::hdd();
+        let transfer = Duration::from_nanos(8 * 1_000_000_000 / profile.bytes_per_second);
+        assert_eq!(
+            profile.seek_settle * 2
+                + profile.seek_per_page * 5
+                + (profile.write_latency + transfer) * 2,
+            write_pages(&[3, 1], profile)
+        );
+    }
+
+    #[test]
+    fn ssd_ignores_order() {
+        assert_eq!(
+            write_pages(&[1, 2, 3, 4, 5], DeviceProfile::nvme()),
+            write_pages(&[5, 1, 4, 2, 3], DeviceProfile::nvme())
+        );
+        assert!(
+            write_pages(&[1, 2, 3], DeviceProfile::nvme())
+                < write_pages(&[1, 2, 3], DeviceProfile::sata_ssd())
+        );
+    }
+
+    #[test]
+    fn failed_operations_are_free() {
+        let mut dm = SimulatedDiskManager::new(DiskManagerMock::new(), DeviceProfile::sata_ssd());
+        assert!(read(&mut dm, 1).is_err());
+        assert!(dm.deallocate_page(1).is_err());
+        assert_eq!(Duration::ZERO, dm.elapsed());
+    }
+
+    #[test]
+    fn failed_operations_keep_the_head() {
+        let profile = DeviceProfile::hdd();
+        let mut dm = SimulatedDiskManager::new(DiskManagerMock::new(), profile);
+        dm.inner.allocate_page().unwrap();
+        assert!(read(&mut dm, 5).is_err());
+
+        dm.write_page(&Page::new(1)).unwrap();
+        let transfer = Duration::from_nanos(8 * 1_000_000_000 / profile.bytes_per_second);
+        assert_eq!(
+            profile.seek_settle + profile.seek_per_page + profile.write_latency + transfer,
+            dm.elapsed()
+        );
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let mut bpm = BufferPoolManager::new(SimulatedDiskManager::new(
+            DiskManagerMock::new(),
+            DeviceProfile::nvme(),
+        ));
+        let page_id = bpm.new_page().unwrap().id();
+        bpm.flush_page(page_id).unwrap();
+        bpm.flush_page(page_id).unwrap();
+        bpm.unpin_page(page_id, false).unwrap();
+        bpm.delete_page(page_id).unwrap();
+
+        let dm = bpm
+            .disk_manager
+            .inner()
+            .as_any()
+            .downcast_ref::<SimulatedDiskManager<Box<DiskManagerMock>>>()
+            .unwrap();
+        let profile = DeviceProfile::nvme();
+        assert_eq!(1, dm.stats().allocations.count);
+        assert_eq!(2, dm.stats().writes.count);
+        assert_eq!(1, dm.stats().deallocations.count);
+        assert_eq!(0, dm.stats().reads.count);
+        let transfer = Duration::from_nanos(8 * 1_000_000_000 / profile.bytes_per_second);
+        assert_eq!((profile.write_latency + transfer) * 4, dm.elapsed());
+    }
+
+    #[test]
+    fn header_page() {
+        let path = data_file("simulated_header_page");
+        let mut dm =
+            SimulatedDiskManager::new(FileDiskManager::open(&path).unwrap(), DeviceProfile::nvme());
+        dm.allocate_page().unwrap();
+        assert_eq!(0b10, dm.header_page().unwrap().data[0]);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::buffer_pool::PageError::{IoError, OutOfStorage, PageNotFound};
+use crate::buffer_pool::{
+    DiskManager, FileDiskManager, Page, PageError, PageId, MAX_NUM_DISK_PAGES,
+};
+use std::any::Any;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{self, Path, PathBuf};
+
+// Each segment is a data file in the `FileDiskManager` format, so it holds at most
+// `MAX_NUM_DISK_PAGES` pages.
+pub const SEGMENT_NUM_PAGES: i32 = MAX_NUM_DISK_PAGES;
+pub const MAX_SEGMENTS_PER_TABLESPACE: i32 = 4096;
+
+// Every tablespace owns a fixed range of page ids:
+// `tablespace * TABLESPACE_NUM_PAGES + segment * SEGMENT_NUM_PAGES + page id within the segment`.
+const TABLESPACE_NUM_PAGES: i32 = SEGMENT_NUM_PAGES * MAX_SEGMENTS_PER_TABLESPACE;
+
+struct Tablespace {
+    name: String,
+    dir: PathBuf,
+    segments: Vec<FileDiskManager>,
+}
+
+impl Tablespace {
+    // Segments are numbered from 0 without gaps. A segment file after a missing one means pages
+    // were lost, so opening fails rather than creating the missing segment anew.
+    fn open(name: &str, dir: &Path) -> Result<Tablespace, PageError> {
+        let mut tablespace = Tablespace {
+            name: name.to_string(),
+            dir: dir.to_path_buf(),
+            segments: Vec::new(),
+        };
+        while tablespace.segment_path(tablespace.segments.len()).exists() {
+            tablespace.add_segment()?;
+        }
+        for entry in fs::read_dir(dir)? {
+            let file_name = entry?.file_name();
+            match tablespace.segment_number(&file_name.to_string_lossy()) {
+                Some(segment) if segment >= tablespace.segments.len() => {
+                    return Err(IoError(ErrorKind::NotFound))
+                }
+                _ => {}
+            }
+        }
+        Ok(tablespace)
+    }
+
+    fn segment_number(&self, file_name: &str) -> Option<usize> {
+        file_name
+            .strip_prefix(&self.name)?
+            .strip_prefix('.')?
+            .parse()
+            .ok()
+    }
+
+    fn segment_path(&self, segment: usize) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.name, segment))
+    }
+
+    fn add_segment(&mut self) -> Result<(), PageError> {
+        let path = self.segment_path(self.segments.len());
+        self.segments.push(*FileDiskManager::open(path)?);
+        Ok(())
+    }
+}
+
+// Spreads pages over several named tablespaces, each a directory of fixed size segment files.
+// A new segment is created when all existing segments of a tablespace are full.
+//
+// Tablespaces are numbered in the order they are added, and that number is part of their page
+// ids, so they must be added in the same order every time the data is opened.
+pub struct TablespaceDiskManager {
+    tablespaces: Vec<Tablespace>,
+    default_tablespace: usize,
+}
+
+impl TablespaceDiskManager {
+    pub fn new() -> Box<TablespaceDiskManager> {
+        Box::new(TablespaceDiskManager {
+            tablespaces: Vec::new(),
+            default_tablespace: 0,
+        })
+    }
+
+    // Opens the tablespace's existing segments in `dir`, if any. Every tablespace needs its own
+    // name and directory, so that no two of them share segment files. The name becomes part of
+    // the segment file names, so it can't be empty or contain a path separator.
+    pub fn add_tablespace<P: AsRef<Path>>(&mut self, name: &str, dir: P) -> Result<(), PageError> {
+        if name.is_empty() || name.contains("..") || name.contains(path::is_separator) {
+            return Err(IoError(ErrorKind::InvalidInput));
+        }
+        let dir = dir.as_ref();
+        let canonical_dir = dir.canonicalize()?;
+        for tablespace in self.tablespaces.iter() {
+            if tablespace.name == name || tablespace.dir.canonicalize()? == canonical_dir {
+                return Err(IoError(ErrorKind::AlreadyExists));
+            }
+        }
+        let tablespace = Tablespace::open(name, dir)?;
+        self.tablespaces.push(tablespace);
+        Ok(())
+    }
+
+    // Chooses where `allocate_page` places new pages. The first tablespace added is the default.
+    pub fn set_default_tablespace(&mut self, name: &str) -> Result<(), PageError> {
+        self.default_tablespace = self.tablespace_index(name)?;
+        Ok(())
+    }
+
+    pub fn allocate_page_in(&mut self, name: &str) -> Result<PageId, PageError> {
+        let index = self.tablespace_index(name)?;
+        let tablespace = &mut self.tablespaces[index];
+        for (segment, disk_manager) in tablespace.segments.iter_mut().enumerate() {
+            match disk_manager.allocate_page() {
+                Ok(id) => return Ok(TablespaceDiskManager::page_id(index, segment, id)),
+                Err(OutOfStorage) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if tablespace.segments.len() >= MAX_SEGMENTS_PER_TABLESPACE as usize {
+            return Err(OutOfStorage);
+        }
+        tablespace.add_segment()?;
+        let segment = tablespace.segments.len() - 1;
+        let id = tablespace.segments[segment].allocate_page()?;
+        Ok(TablespaceDiskManager::page_id(index, segment, id))
+    }
+
+    // Returns the tablespace name, segment file and page id within the segment for a page.
+    pub fn locate(&self, id: PageId) -> Option<(&str, PathBuf, PageId)> {
+        let (index, segment, local_id) = self.split(id)?;
+        let tablespace = &self.tablespaces[index];
+        Some((&tablespace.name, tablespace.segment_path(segment), local_id))
+    }
+
+    fn tablespace_index(&self, name: &str) -> Result<usize, PageError> {
+        self.tablespaces
+            .iter()
+            .position(|tablespace| tablespace.name == name)
+            .ok_or(PageNotFound)
+    }
+
+    fn page_id(tablespace: usize, segment: usize, local_id: PageId) -> PageId {
+        tablespace as i32 * TABLESPACE_NUM_PAGES + segment as i32 * SEGMENT_NUM_PAGES + local_id
+    }
+
+    fn split(&self, id: PageId) -> Option<(usize, usize, PageId)> {
+        if id < 1 {
+            return None;
+        }
+        let index = (id - 1) / TABLESPACE_NUM_PAGES;
+        let offset = (id - 1) % TABLESPACE_NUM_PAGES;
+        let segment = offset / SEGMENT_NUM_PAGES;
+        let tablespace = self.tablespaces.get(index as usize)?;
+        if segment as usize >= tablespace.segments.len() {
+            return None;
+        }
+        Some((
+            index as usize,
+            segment as usize,
+            offset % SEGMENT_NUM_PAGES + 1,
+        ))
+    }
+
+    fn segment(&mut self, id: PageId) -> Result<(&mut FileDiskManager, PageId), PageError> {
+        let (index, segment, local_id) = self.split(id).ok_or(PageNotFound)?;
+        Ok((&mut self.tablespaces[index].segments[segment], local_id))
+    }
+}
+
+impl DiskManager for TablespaceDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        let (segment, local_id) = self.segment(id)?;
+        segment.read_page(local_id, page)?;
+        page.id = id;
+        Ok(())
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        let (segment, local_id) = self.segment(page.id)?;
+        let mut local = *page;
+        local.id = local_id;
+        segment.write_page(&local)
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        for tablespace in self.tablespaces.iter_mut() {
+            for segment in tablespace.segments.iter_mut() {
+                segment.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let name = match self.tablespaces.get(self.default_tablespace) {
+            Some(tablespace) => tablespace.name.clone(),
+            None => return Err(OutOfStorage),
+        };
+        self.allocate_page_in(&name)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        let (segment, local_id) = self.segment(id)?;
+        segment.deallocate_page(local_id)
+    }
+
+    // Pages only move within their segment.
+    fn compact(&mut self) -> Result<Vec<(PageId, PageId)>, PageError> {
+        let mut moves = Vec::new();
+        for (index, tablespace) in self.tablespaces.iter_mut().enumerate() {
+            for (segment, disk_manager) in tablespace.segments.iter_mut().enumerate() {
+                for (old_id, new_id) in disk_manager.compact()? {
+                    moves.push((
+                        TablespaceDiskManager::page_id(index, segment, old_id),
+                        TablespaceDiskManager::page_id(index, segment, new_id),
+                    ));
+                }
+            }
+        }
+        Ok(moves)
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        let mut pages = Vec::new();
+        for (index, tablespace) in self.tablespaces.iter().enumerate() {
+            for (segment, disk_manager) in tablespace.segments.iter().enumerate() {
+                for id in disk_manager.pages_on_disk() {
+                    pages.push(TablespaceDiskManager::page_id(index, segment, id));
+                }
+            }
+        }
+        pages
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::{IoError, PageNotFound};
+    use crate::buffer_pool::tablespace_disk_manager::TABLESPACE_NUM_PAGES;
+    use crate::buffer_pool::tests::read;
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManager, Page, TablespaceDiskManager, SEGMENT_NUM_PAGES,
+    };
+    use std::env;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+
+    // A directory in the temp directory that is removed again with its contents when dropped.
+    struct DataDir(PathBuf);
+
+    impl Deref for DataDir {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for DataDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for DataDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn data_dir(name: &str) -> DataDir {
+        let path = env::temp_dir().join(format!("buffer_pool_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        DataDir(path)
+    }
+
+    fn open(users: &Path, logs: &Path) -> Box<TablespaceDiskManager> {
+        let mut dm = TablespaceDiskManager::new();
+        dm.add_tablespace("users", users).unwrap();
+        dm.add_tablespace("logs", logs).unwrap();
+        dm
+    }
+
+    #[test]
+    fn segments_and_tablespaces() {
+        let users = data_dir("tablespace_users");
+        let logs = data_dir("tablespace_logs");
+        {
+            let mut dm = open(&users, &logs);
+            for i in 1..=SEGMENT_NUM_PAGES + 2 {
+                let id = dm.allocate_page().unwrap();
+                assert_eq!(i, id);
+                let mut page = Page::new(id);
+                page.data = [i as u8; 8];
+                dm.write_page(&page).unwrap();
+            }
+            assert!(users.join("users.1").exists());
+            assert!(!users.join("users.2").exists());
+
+            let id = dm.allocate_page_in("logs").unwrap();
+            assert_eq!(TABLESPACE_NUM_PAGES + 1, id);
+            let (name, path, local_id) = dm.locate(id).unwrap();
+            assert_eq!(("logs", logs.join("logs.0"), 1), (name, path, local_id));
+            assert_eq!(PageNotFound, dm.allocate_page_in("missing").unwrap_err());
+        }
+
+        let mut dm = open(&users, &logs);
+        assert_eq!(SEGMENT_NUM_PAGES as usize + 3, dm.pages_on_disk().len());
+        for i in 1..=SEGMENT_NUM_PAGES + 2 {
+            let page = read(&mut dm, i).unwrap();
+            assert_eq!(i, page.id());
+            assert_eq!([i as u8; 8], page.data);
+        }
+
+        dm.deallocate_page(3).unwrap();
+        assert_eq!(3, dm.allocate_page().unwrap());
+
+        dm.set_default_tablespace("logs").unwrap();
+        assert_eq!(TABLESPACE_NUM_PAGES + 2, dm.allocate_page().unwrap());
+        assert_eq!(
+            PageNotFound,
+            read(&mut dm, 2 * TABLESPACE_NUM_PAGES).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn duplicate_tablespaces() {
+        let users = data_dir("tablespace_duplicate_users");
+        let logs = data_dir("tablespace_duplicate_logs");
+        let other = data_dir("tablespace_duplicate_other");
+        let mut dm = open(&users, &logs);
+        assert_eq!(
+            IoError(ErrorKind::AlreadyExists),
+            dm.add_tablespace("users", &other).unwrap_err()
+        );
+        assert_eq!(
+            IoError(ErrorKind::AlreadyExists),
+            dm.add_tablespace("other", users.join(".")).unwrap_err()
+        );
+        dm.add_tablespace("other", &other).unwrap();
+    }
+
+    #[test]
+    fn invalid_names() {
+        let dir = data_dir("tablespace_invalid_names");
+        let mut dm = TablespaceDiskManager::new();
+        for name in ["", "..", "../users", "a/b"].iter() {
+            assert_eq!(
+                IoError(ErrorKind::InvalidInput),
+                dm.add_tablespace(name, &dir).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn missing_segment() {
+        let users = data_dir("tablespace_missing_users");
+        let logs = data_dir("tablespace_missing_logs");
+        {
+            let mut dm = open(&users, &logs);
+            for _ in 0..=SEGMENT_NUM_PAGES * 2 {
+                dm.allocate_page().unwrap();
+            }
+        }
+        fs::remove_file(users.join("users.1")).unwrap();
+
+        let mut dm = TablespaceDiskManager::new();
+        assert_eq!(
+            IoError(ErrorKind::NotFound),
+            dm.add_tablespace("users", &users).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let users = data_dir("tablespace_bpm_users");
+        let logs = data_dir("tablespace_bpm_logs");
+        {
+            let mut bpm = BufferPoolManager::new(open(&users, &logs));
+            let page = bpm.new_page().unwrap();
+            page.data = *b"tablespc";
+            bpm.flush_all_pages().unwrap();
+        }
+
+        let mut bpm = BufferPoolManager::new(open(&users, &logs));
+        assert_eq!(*b"tablespc", bpm.fetch_page(1).unwrap().data);
+    }
+}
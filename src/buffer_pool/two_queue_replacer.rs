@@ -0,0 +1,404 @@
+use crate::buffer_pool::{FrameId, PageId, Replacer, ReplacerRep};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+// The full 2Q algorithm (Johnson and Shasha). A page seen for the first time goes to the A1in
+// FIFO, and when it is evicted from there only its page id is remembered, in the A1out FIFO. A
+// page that comes back while still remembered is hot and goes to Am, which is managed as LRU.
+// Pages from A1in are evicted first once it holds more than `kin` frames, otherwise from Am, so
+// pages used only once never push out hot ones.
+pub struct TwoQueueReplacer {
+    kin: usize,
+    kout: usize,
+    // Oldest first.
+    a1in: VecDeque<FrameId>,
+    // Page ids, oldest first.
+    a1out: VecDeque<PageId>,
+    // Least recently used first.
+    am: VecDeque<FrameId>,
+    page_ids: HashMap<FrameId, PageId>,
+    evictable: BTreeSet<FrameId>,
+    // Victims, with the queue and position they were taken from. Their pages are only
+    // remembered once a new page is read into the frame.
+    evicted: HashMap<FrameId, (bool, usize)>,
+}
+
+impl TwoQueueReplacer {
+    pub fn new(kin: usize, kout: usize) -> TwoQueueReplacer {
+        TwoQueueReplacer {
+            kin,
+            kout,
+            a1in: VecDeque::new(),
+            a1out: VecDeque::new(),
+            am: VecDeque::new(),
+            page_ids: HashMap::new(),
+            evictable: BTreeSet::new(),
+            evicted: HashMap::new(),
+        }
+    }
+
+    fn remove_from(queue: &mut VecDeque<FrameId>, id: FrameId) -> bool {
+        match queue.iter().position(|frame_id| *frame_id == id) {
+            Some(index) => queue.remove(index).is_some(),
+            None => false,
+        }
+    }
+
+    fn take_evictable(&mut self, from_a1in: bool) -> Option<(FrameId, usize)> {
+        let evictable = &self.evictable;
+        let queue = if from_a1in {
+            &mut self.a1in
+        } else {
+            &mut self.am
+        };
+        let index = queue.iter().position(|id| evictable.contains(id))?;
+        Some((queue.remove(index)?, index))
+    }
+
+    // The page of a victim was written back and is gone, only its id is kept if it was evicted
+    // from A1in.
+    fn evict(&mut self, id: FrameId, from_a1in: bool) {
+        if let Some(page_id) = self.page_ids.remove(&id) {
+            if from_a1in && self.kout > 0 {
+                if self.a1out.len() == self.kout {
+                    self.a1out.pop_front();
+                }
+                self.a1out.push_back(page_id);
+            }
+        }
+    }
+}
+
+impl Replacer for TwoQueueReplacer {
+    // Falls back to the other queue when every frame in the preferred one is pinned.
+    fn victim(&mut self) -> Option<FrameId> {
+        let from_a1in = self.a1in.len() > self.kin;
+        let (id, index, from_a1in) = match self.take_evictable(from_a1in) {
+            Some((id, index)) => (id, index, from_a1in),
+            None => {
+                let (id, index) = self.take_evictable(!from_a1in)?;
+                (id, index, !from_a1in)
+            }
+        };
+        self.evictable.remove(&id);
+        self.evicted.insert(id, (from_a1in, index));
+        Some(id)
+    }
+
+    // A frame unpinned before any access is treated as holding a page seen for the first time. A
+    // victim that is unpinned again still holds its page, because writing it back failed, so it
+    // goes back to where it was.
+    fn unpin(&mut self, id: FrameId) {
+        if let Some((from_a1in, index)) = self.evicted.remove(&id) {
+            let queue = if from_a1in {
+                &mut self.a1in
+            } else {
+                &mut self.am
+            };
+            queue.insert(index.min(queue.len()), id);
+        } else if !self.a1in.contains(&id) && !self.am.contains(&id) {
+            self.a1in.push_back(id);
+        }
+        self.evictable.insert(id);
+    }
+
+    fn pin(&mut self, id: FrameId) {
+        self.evictable.remove(&id);
+    }
+
+    fn record_access(&mut self, id: FrameId, page_id: PageId) {
+        if let Some((from_a1in, _)) = self.evicted.remove(&id) {
+            self.evict(id, from_a1in);
+        }
+        if self.page_ids.get(&id) == Some(&page_id) {
+            // Repeated accesses while in A1in are correlated and don't make the page hot.
+            if TwoQueueReplacer::remove_from(&mut self.am, id) {
+                self.am.push_back(id);
+            }
+            return;
+        }
+
+        // The frame holds a new page.
+        TwoQueueReplacer::remove_from(&mut self.a1in, id);
+        TwoQueueReplacer::remove_from(&mut self.am, id);
+        match self.a1out.iter().position(|ghost| *ghost == page_id) {
+            Some(index) => {
+                self.a1out.remove(index);
+                self.am.push_back(id);
+            }
+            None => self.a1in.push_back(id),
+        }
+        self.page_ids.insert(id, page_id);
+    }
+
+    fn remove(&mut self, id: FrameId) {
+        TwoQueueReplacer::remove_from(&mut self.a1in, id);
+        TwoQueueReplacer::remove_from(&mut self.am, id);
+        self.page_ids.remove(&id);
+        self.evictable.remove(&id);
+        self.evicted.remove(&id);
+    }
+
+    fn forget_page(&mut self, page_id: PageId) {
+        self.a1out.retain(|ghost| *ghost != page_id);
+        // A victim whose page is deleted before a new page is read into the frame.
+        let evicted = &self.evicted;
+        self.page_ids
+            .retain(|id, held| *held != page_id || !evicted.contains_key(id));
+    }
+
+    fn remap(&mut self, moves: &[(PageId, PageId)]) {
+        let moves: HashMap<PageId, PageId> = moves.iter().copied().collect();
+        for page_id in self.a1out.iter_mut().chain(self.page_ids.values_mut()) {
+            if let Some(new_id) = moves.get(page_id) {
+                *page_id = *new_id;
+            }
+        }
+    }
+
+    fn response(&self) -> ReplacerRep {
+        let rep = TwoQueueReplacerRep {
+            kin: self.kin,
+            kout: self.kout,
+            a1in: self.a1in.iter().copied().collect(),
+            a1out: self.a1out.iter().copied().collect(),
+            am: self.am.iter().copied().collect(),
+        };
+        ReplacerRep::new("TwoQueueReplacer", &rep)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TwoQueueReplacerRep {
+    #[serde(rename = "Kin")]
+    pub kin: usize,
+    #[serde(rename = "Kout")]
+    pub kout: usize,
+    // Frames, oldest first.
+    #[serde(rename = "A1in")]
+    pub a1in: Vec<FrameId>,
+    // Page ids of pages evicted from A1in, oldest first.
+    #[serde(rename = "A1out")]
+    pub a1out: Vec<PageId>,
+    // Frames, least recently used first.
+    #[serde(rename = "Am")]
+    pub am: Vec<FrameId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::{
+        BufferPoolManager, DiskManagerMock, FaultyDiskManager, Operation, Replacer,
+        TwoQueueReplacer, TwoQueueReplacerRep, MAX_POOL_SIZE,
+    };
+
+    fn rep(r: &TwoQueueReplacer) -> TwoQueueReplacerRep {
+        r.response().state("TwoQueueReplacer").unwrap()
+    }
+
+    fn pool_rep(bpm: &BufferPoolManager) -> TwoQueueReplacerRep {
+        bpm.response().replacer.state("TwoQueueReplacer").unwrap()
+    }
+
+    #[test]
+    fn two_queue_replacer() {
+        let mut r = TwoQueueReplacer::new(1, 2);
+        for (frame, page) in [(0, 10), (1, 11), (2, 12)].iter() {
+            r.record_access(*frame, *page);
+            r.record_access(*frame, *page);
+            r.unpin(*frame);
+        }
+        assert_eq!(vec![0, 1, 2], rep(&r).a1in);
+        assert!(rep(&r).am.is_empty());
+
+        // A1in is over its size, so its oldest frames go first. Their pages are remembered once
+        // new pages are read into the frames.
+        assert_eq!(Some(0), r.victim());
+        assert_eq!(Some(1), r.victim());
+        assert!(rep(&r).a1out.is_empty());
+
+        // Page 10 comes back while still remembered and is now hot.
+        r.record_access(0, 10);
+        r.record_access(1, 13);
+        assert_eq!(vec![2, 1], rep(&r).a1in);
+        assert_eq!(vec![11], rep(&r).a1out);
+        assert_eq!(vec![0], rep(&r).am);
+        r.unpin(0);
+        r.unpin(1);
+
+        assert_eq!(Some(2), r.victim());
+        // A1in is within its size now, so Am is evicted from, unless all of it is pinned.
+        r.pin(0);
+        assert_eq!(Some(1), r.victim());
+        r.unpin(0);
+        assert_eq!(Some(0), r.victim());
+        assert_eq!(None, r.victim());
+
+        // Only the pages evicted from A1in are remembered.
+        r.record_access(2, 14);
+        r.record_access(1, 15);
+        r.record_access(0, 16);
+        assert_eq!(vec![12, 13], rep(&r).a1out);
+    }
+
+    #[test]
+    fn lru_main_queue() {
+        let mut r = TwoQueueReplacer::new(0, 4);
+        for frame in 0..3 {
+            r.record_access(frame, frame + 10);
+            r.unpin(frame);
+            assert_eq!(Some(frame), r.victim());
+            r.record_access(frame, frame + 10);
+            r.unpin(frame);
+        }
+        assert_eq!(vec![0, 1, 2], rep(&r).am);
+        r.record_access(0, 10);
+
+        assert_eq!(Some(1), r.victim());
+        assert_eq!(Some(2), r.victim());
+        assert_eq!(Some(0), r.victim());
+    }
+
+    #[test]
+    fn deleted_page() {
+        let mut r = TwoQueueReplacer::new(0, 2);
+        r.record_access(0, 10);
+        r.unpin(0);
+        assert_eq!(Some(0), r.victim());
+        r.record_access(0, 10);
+        assert_eq!(vec![0], rep(&r).am);
+
+        // Deleted while in a frame, so a new page with the same id in that frame is new.
+        r.remove(0);
+        r.forget_page(10);
+        r.record_access(0, 10);
+        assert_eq!(vec![0], rep(&r).a1in);
+        assert!(rep(&r).am.is_empty());
+
+        // Deleted after being evicted, so it is no longer remembered.
+        r.unpin(0);
+        assert_eq!(Some(0), r.victim());
+        r.forget_page(10);
+        assert!(rep(&r).a1out.is_empty());
+        r.record_access(0, 10);
+        assert_eq!(vec![0], rep(&r).a1in);
+    }
+
+    #[test]
+    fn remapped_pages() {
+        let mut r = TwoQueueReplacer::new(0, 2);
+        for frame in 0..2 {
+            r.record_access(frame, frame + 10);
+            r.unpin(frame);
+            assert_eq!(Some(frame), r.victim());
+        }
+        r.record_access(0, 10);
+        r.record_access(1, 12);
+        assert_eq!(vec![11], rep(&r).a1out);
+
+        r.remap(&[(10, 1), (11, 2)]);
+        assert_eq!(vec![2], rep(&r).a1out);
+        // Still the same page, so it stays hot.
+        r.record_access(0, 1);
+        assert_eq!(vec![0], rep(&r).am);
+        r.record_access(1, 2);
+        assert_eq!(vec![0, 1], rep(&r).am);
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let replacer = Box::new(TwoQueueReplacer::new(1, 2));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.flush_page(id).unwrap();
+            bpm.unpin_page(id, false).unwrap();
+        }
+
+        // Page 1 is evicted from A1in and read back in while remembered.
+        assert_eq!(5, bpm.new_page().unwrap().id());
+        bpm.unpin_page(5, false).unwrap();
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        let rep = pool_rep(&bpm);
+        assert!(rep.am.len() == 1 && rep.a1out == vec![2]);
+
+        // A scan through new pages doesn't push page 1 out.
+        for _ in 0..4 {
+            let id = bpm.new_page().unwrap().id();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        assert!(bpm.page_table.contains_key(&1));
+    }
+
+    #[test]
+    fn failed_eviction() {
+        let mut dm = FaultyDiskManager::new(DiskManagerMock::new());
+        dm.fail(Operation::Write).on_page(1).times(1);
+        let replacer = Box::new(TwoQueueReplacer::new(1, 2));
+        let mut bpm = BufferPoolManager::with_replacer(dm, replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.unpin_page(id, false).unwrap();
+        }
+
+        // Page 1 can't be written back, so it stays where it was and isn't remembered.
+        assert!(bpm.new_page().is_err());
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        let rep = pool_rep(&bpm);
+        assert!(rep.a1in == vec![0, 1, 2, 3] && rep.a1out.is_empty() && rep.am.is_empty());
+    }
+
+    #[test]
+    fn reuse_deleted_page_id() {
+        let replacer = Box::new(TwoQueueReplacer::new(1, 2));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.flush_page(id).unwrap();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        assert_eq!(5, bpm.new_page().unwrap().id());
+        bpm.unpin_page(5, false).unwrap();
+
+        // Page 1 was evicted and is deleted, so the next page to get its id isn't hot.
+        bpm.delete_page(1).unwrap();
+        assert_eq!(1, bpm.new_page().unwrap().id());
+        let rep = pool_rep(&bpm);
+        assert!(rep.am.is_empty() && rep.a1out == vec![2]);
+    }
+
+    #[test]
+    fn compact() {
+        let replacer = Box::new(TwoQueueReplacer::new(1, 2));
+        let mut bpm = BufferPoolManager::with_replacer(DiskManagerMock::new(), replacer);
+        for _ in 0..MAX_POOL_SIZE {
+            bpm.new_page().unwrap();
+        }
+        for id in 1..=MAX_POOL_SIZE as i32 {
+            bpm.flush_page(id).unwrap();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        for _ in 0..2 {
+            let id = bpm.new_page().unwrap().id();
+            bpm.unpin_page(id, false).unwrap();
+        }
+        // Page 2 is hot, page 3 is remembered.
+        bpm.fetch_page(2).unwrap();
+        bpm.unpin_page(2, false).unwrap();
+        bpm.delete_page(1).unwrap();
+
+        assert_eq!((2, 1), bpm.compact().unwrap()[0]);
+        bpm.fetch_page(1).unwrap();
+        bpm.unpin_page(1, false).unwrap();
+        let rep = pool_rep(&bpm);
+        assert!(rep.am.len() == 1 && rep.a1out == vec![2]);
+    }
+}
@@ -0,0 +1,525 @@
+use crate::buffer_pool::file_io::{BufferedIo, FileIo};
+use crate::buffer_pool::PageError::{Corrupted, OutOfStorage, PageNotFound, VersionPruned};
+use crate::buffer_pool::{DiskManager, Page, PageError, PageId, MAX_NUM_DISK_PAGES, PAGE_SIZE};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+const CHECKSUM_SIZE: usize = 4;
+const BATCH_HEADER_SIZE: usize = 8 + 4;
+const ENTRY_SIZE: usize = 4 + 1 + PAGE_SIZE;
+
+const WRITTEN: u8 = 0;
+const DEALLOCATED: u8 = 1;
+// Written first by `prune`, its version is the oldest one that can still be read.
+const PRUNED: u8 = 2;
+
+type Entry = (PageId, u8, [u8; PAGE_SIZE]);
+
+fn batch_size(entries: usize) -> usize {
+    BATCH_HEADER_SIZE + entries * ENTRY_SIZE + CHECKSUM_SIZE
+}
+
+// Every change made in one version, stored as a single record so that a version is either
+// in the log completely or not at all.
+struct Batch {
+    version: u64,
+    entries: Vec<Entry>,
+}
+
+impl Batch {
+    // Version and number of entries, then the page id, kind and data of each entry, little
+    // endian, followed by a CRC32C of all of them.
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (id, kind, data) in self.entries.iter() {
+            bytes.extend_from_slice(&id.to_le_bytes());
+            bytes.push(*kind);
+            bytes.extend_from_slice(data);
+        }
+        let checksum = crc32c::crc32c(&bytes[start..]);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+    }
+
+    // Decodes the batch at the start of `bytes`, which may be followed by more batches.
+    fn decode(bytes: &[u8]) -> Option<Batch> {
+        let mut count = [0; 4];
+        count.copy_from_slice(bytes.get(8..BATCH_HEADER_SIZE)?);
+        let size = batch_size(u32::from_le_bytes(count) as usize);
+        let bytes = bytes.get(..size)?;
+        let mut checksum = [0; CHECKSUM_SIZE];
+        checksum.copy_from_slice(&bytes[size - CHECKSUM_SIZE..]);
+        if u32::from_le_bytes(checksum) != crc32c::crc32c(&bytes[..size - CHECKSUM_SIZE]) {
+            return None;
+        }
+        let mut version = [0; 8];
+        version.copy_from_slice(&bytes[..8]);
+        let entries = bytes[BATCH_HEADER_SIZE..size - CHECKSUM_SIZE]
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let mut id = [0; 4];
+                id.copy_from_slice(&entry[..4]);
+                let mut data = [0; PAGE_SIZE];
+                data.copy_from_slice(&entry[5..]);
+                (PageId::from_le_bytes(id), entry[4], data)
+            })
+            .collect();
+        Some(Batch {
+            version: u64::from_le_bytes(version),
+            entries,
+        })
+    }
+}
+
+// Every change of a page, as the version it was made in and the offset of its batch in the
+// log, or `None` when the page was deallocated.
+type History = Vec<(u64, Option<u64>)>;
+
+// Appends every page write to a log instead of overwriting the page, so earlier versions of a
+// page can still be read with `read_page_as_of`. Each allocation, deallocation and write gets
+// the next version, except that the pages of a single `write_pages` call share one. Every
+// version is appended as one batch and synced before the call returns.
+//
+// The log only grows until `prune` drops the versions that are no longer needed.
+pub struct VersionedDiskManager {
+    path: PathBuf,
+    io: Box<dyn FileIo>,
+    len: u64,
+    version: u64,
+    oldest_version: u64,
+    pages: BTreeMap<PageId, History>,
+}
+
+impl VersionedDiskManager {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Box<VersionedDiskManager>, PageError> {
+        let path = path.as_ref().to_path_buf();
+        let io: Box<dyn FileIo> = Box::new(BufferedIo::open(&path)?);
+        let mut manager = Box::new(VersionedDiskManager {
+            path,
+            io,
+            len: 0,
+            version: 0,
+            oldest_version: 0,
+            pages: BTreeMap::new(),
+        });
+        manager.load()?;
+        Ok(manager)
+    }
+
+    // The version of the latest change.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // The oldest version `read_page_as_of` can still read.
+    pub fn oldest_version(&self) -> u64 {
+        self.oldest_version
+    }
+
+    // The versions in which the page was allocated, written or deallocated, oldest first.
+    pub fn versions(&self, id: PageId) -> Vec<u64> {
+        self.pages
+            .get(&id)
+            .map(|history| history.iter().map(|(version, _)| *version).collect())
+            .unwrap_or_default()
+    }
+
+    // Reads the page as it was right after the given version.
+    pub fn read_page_as_of(
+        &mut self,
+        id: PageId,
+        version: u64,
+        page: &mut Page,
+    ) -> Result<(), PageError> {
+        if version < self.oldest_version {
+            return Err(VersionPruned { version });
+        }
+        let offset = self
+            .pages
+            .get(&id)
+            .and_then(|history| history.iter().rev().find(|(v, _)| *v <= version))
+            .and_then(|(_, offset)| *offset)
+            .ok_or(PageNotFound)?;
+        self.read_record(id, offset, page)
+    }
+
+    fn read_record(&mut self, id: PageId, offset: u64, page: &mut Page) -> Result<(), PageError> {
+        let mut header = [0; BATCH_HEADER_SIZE];
+        self.io.read_at(&mut header, offset)?;
+        let mut count = [0; 4];
+        count.copy_from_slice(&header[8..]);
+        let size = batch_size(u32::from_le_bytes(count) as usize);
+        if offset + size as u64 > self.len {
+            return Err(Corrupted { page_id: id });
+        }
+        let mut bytes = vec![0; size];
+        self.io.read_at(&mut bytes, offset)?;
+        let entry = Batch::decode(&bytes).and_then(|batch| {
+            batch
+                .entries
+                .into_iter()
+                .rev()
+                .find(|(entry_id, kind, _)| *entry_id == id && *kind == WRITTEN)
+        });
+        match entry {
+            Some((_, _, data)) => {
+                page.id = id;
+                page.data = data;
+                Ok(())
+            }
+            None => Err(Corrupted { page_id: id }),
+        }
+    }
+
+    // Drops every version older than `before` that isn't needed to read the pages as of
+    // `before`, and rewrites the log without them. Returns how many versions were dropped.
+    //
+    // Like `FileDiskManager::upgrade`, the new log is written next to the old one and renamed
+    // over it.
+    pub fn prune(&mut self, before: u64) -> Result<usize, PageError> {
+        let before = before.min(self.version);
+        if before <= self.oldest_version {
+            return Ok(0);
+        }
+        let mut log = Vec::new();
+        Batch {
+            version: before,
+            entries: vec![(0, PRUNED, [0; PAGE_SIZE])],
+        }
+        .encode(&mut log);
+
+        let mut pruned = 0;
+        let mut pages = BTreeMap::new();
+        let ids: Vec<PageId> = self.pages.keys().copied().collect();
+        for id in ids {
+            let history = &self.pages[&id];
+            let keep_from = history
+                .iter()
+                .rposition(|(version, _)| *version <= before)
+                .unwrap_or(0);
+            let mut kept = History::new();
+            for (index, (version, offset)) in history.clone().into_iter().enumerate() {
+                // A page deallocated as of `before` doesn't need any history before that.
+                if index < keep_from
+                    || (index == keep_from && version <= before && offset.is_none())
+                {
+                    pruned += 1;
+                    continue;
+                }
+                let mut page = *Page::new(id);
+                let kind = match offset {
+                    Some(offset) => {
+                        self.read_record(id, offset, &mut page)?;
+                        WRITTEN
+                    }
+                    None => DEALLOCATED,
+                };
+                kept.push((version, offset.map(|_| log.len() as u64)));
+                Batch {
+                    version,
+                    entries: vec![(id, kind, page.data)],
+                }
+                .encode(&mut log);
+            }
+            if !kept.is_empty() {
+                pages.insert(id, kept);
+            }
+        }
+
+        let mut prune_path: OsString = self.path.as_os_str().to_owned();
+        prune_path.push(".prune");
+        let prune_path = PathBuf::from(prune_path);
+        fs::write(&prune_path, &log)?;
+        File::open(&prune_path)?.sync_all()?;
+        fs::rename(&prune_path, &self.path)?;
+        self.io = Box::new(BufferedIo::open(&self.path)?);
+        self.len = log.len() as u64;
+        self.oldest_version = before;
+        self.pages = pages;
+        Ok(pruned)
+    }
+
+    // Replays the log up to the first batch that is cut short or fails its checksum, which is
+    // cut off. Appends are synced one by one, so only the last one can have been torn by a crash.
+    fn load(&mut self) -> Result<(), PageError> {
+        let mut log = vec![0; self.io.len()? as usize];
+        self.io.read_at(&mut log, 0)?;
+        let mut offset = 0;
+        while let Some(batch) = Batch::decode(&log[offset..]) {
+            self.version = self.version.max(batch.version);
+            for (id, kind, _) in batch.entries.iter() {
+                match *kind {
+                    PRUNED => self.oldest_version = batch.version,
+                    DEALLOCATED => self.history(*id).push((batch.version, None)),
+                    _ => self.history(*id).push((batch.version, Some(offset as u64))),
+                }
+            }
+            offset += batch_size(batch.entries.len());
+        }
+        self.len = offset as u64;
+        if offset < log.len() {
+            self.io.set_len(self.len)?;
+        }
+        Ok(())
+    }
+
+    fn history(&mut self, id: PageId) -> &mut History {
+        self.pages.entry(id).or_default()
+    }
+
+    fn is_allocated(&self, id: PageId) -> bool {
+        self.pages
+            .get(&id)
+            .and_then(|history| history.last())
+            .is_some_and(|(_, offset)| offset.is_some())
+    }
+
+    // Appends the pages as one batch under the next version.
+    fn append(&mut self, entries: &[Entry]) -> Result<(), PageError> {
+        let version = self.version + 1;
+        let mut log = Vec::with_capacity(batch_size(entries.len()));
+        Batch {
+            version,
+            entries: entries.to_vec(),
+        }
+        .encode(&mut log);
+        self.io.write_at(&log, self.len)?;
+        self.io.sync()?;
+        for (id, kind, _) in entries {
+            let offset = if *kind == DEALLOCATED {
+                None
+            } else {
+                Some(self.len)
+            };
+            self.history(*id).push((version, offset));
+        }
+        self.len += log.len() as u64;
+        self.version = version;
+        Ok(())
+    }
+}
+
+impl DiskManager for VersionedDiskManager {
+    fn read_page(&mut self, id: PageId, page: &mut Page) -> Result<(), PageError> {
+        self.read_page_as_of(id, self.version, page)
+    }
+
+    fn write_page(&mut self, page: &Page) -> Result<(), PageError> {
+        self.write_pages(&[page])
+    }
+
+    fn write_pages(&mut self, pages: &[&Page]) -> Result<(), PageError> {
+        if pages.iter().any(|page| !self.is_allocated(page.id)) {
+            return Err(PageNotFound);
+        }
+        let records: Vec<_> = pages
+            .iter()
+            .map(|page| (page.id, WRITTEN, page.data))
+            .collect();
+        self.append(&records)
+    }
+
+    fn sync(&mut self) -> Result<(), PageError> {
+        self.io.sync()?;
+        Ok(())
+    }
+
+    fn allocate_page(&mut self) -> Result<PageId, PageError> {
+        let id = (1..=MAX_NUM_DISK_PAGES)
+            .find(|id| !self.is_allocated(*id))
+            .ok_or(OutOfStorage)?;
+        self.append(&[(id, WRITTEN, [0; PAGE_SIZE])])?;
+        Ok(id)
+    }
+
+    fn deallocate_page(&mut self, id: PageId) -> Result<(), PageError> {
+        if !self.is_allocated(id) {
+            return Err(PageNotFound);
+        }
+        self.append(&[(id, DEALLOCATED, [0; PAGE_SIZE])])
+    }
+
+    fn pages_on_disk(&self) -> Vec<i32> {
+        (1..=MAX_NUM_DISK_PAGES)
+            .filter(|id| self.is_allocated(*id))
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::page::PageError::{PageNotFound, VersionPruned};
+    use crate::buffer_pool::tests::{data_file, page, read};
+    use crate::buffer_pool::versioned_disk_manager::{batch_size, BATCH_HEADER_SIZE, ENTRY_SIZE};
+    use crate::buffer_pool::{BufferPoolManager, DiskManager, Page, VersionedDiskManager};
+    use std::fs;
+    use std::fs::OpenOptions;
+
+    fn read_as_of(dm: &mut VersionedDiskManager, id: i32, version: u64) -> [u8; 8] {
+        let mut page = *Page::new(0);
+        dm.read_page_as_of(id, version, &mut page).unwrap();
+        page.data
+    }
+
+    #[test]
+    fn read_page_as_of() {
+        let path = data_file("versioned_as_of");
+        {
+            let mut dm = VersionedDiskManager::open(&path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_page(&page(1, b"first   ")).unwrap();
+            dm.write_pages(&[&page(1, b"second  "), &page(2, b"other   ")])
+                .unwrap();
+            dm.deallocate_page(2).unwrap();
+            assert_eq!(5, dm.version());
+        }
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(5, dm.version());
+        assert_eq!(vec![1, 3, 4], dm.versions(1));
+        assert_eq!(vec![2, 4, 5], dm.versions(2));
+        assert_eq!([0; 8], read_as_of(&mut dm, 1, 2));
+        assert_eq!(*b"first   ", read_as_of(&mut dm, 1, 3));
+        assert_eq!(*b"second  ", read_as_of(&mut dm, 1, 4));
+        assert_eq!(*b"other   ", read_as_of(&mut dm, 2, 4));
+        assert_eq!(*b"second  ", read(&mut dm, 1).unwrap().data);
+
+        let mut page = *Page::new(0);
+        assert_eq!(
+            PageNotFound,
+            dm.read_page_as_of(2, 1, &mut page).unwrap_err()
+        );
+        assert_eq!(PageNotFound, read(&mut dm, 2).unwrap_err());
+
+        // Page ids are reused, but their history is kept.
+        assert_eq!(2, dm.allocate_page().unwrap());
+        assert_eq!(*b"other   ", read_as_of(&mut dm, 2, 4));
+    }
+
+    #[test]
+    fn prune() {
+        let path = data_file("versioned_prune");
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        dm.allocate_page().unwrap();
+        dm.allocate_page().unwrap();
+        for data in [b"11111111", b"22222222", b"33333333"].iter() {
+            dm.write_page(&page(1, data)).unwrap();
+        }
+        dm.deallocate_page(2).unwrap();
+        dm.write_page(&page(1, b"44444444")).unwrap();
+
+        // As of version 4 page 1 held its second write and page 2 was still allocated.
+        assert_eq!(2, dm.prune(4).unwrap());
+        assert_eq!(vec![4, 5, 7], dm.versions(1));
+        assert_eq!(vec![2, 6], dm.versions(2));
+        assert_eq!(*b"22222222", read_as_of(&mut dm, 1, 4));
+        assert_eq!(*b"33333333", read_as_of(&mut dm, 1, 5));
+        let mut page = *Page::new(0);
+        assert_eq!(
+            VersionPruned { version: 3 },
+            dm.read_page_as_of(1, 3, &mut page).unwrap_err()
+        );
+
+        assert_eq!(3, dm.prune(6).unwrap());
+        assert!(dm.versions(2).is_empty());
+        assert_eq!(0, dm.prune(6).unwrap());
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(7, dm.version());
+        assert_eq!(6, dm.oldest_version());
+        assert_eq!(vec![5, 7], dm.versions(1));
+        assert_eq!(*b"44444444", read(&mut dm, 1).unwrap().data);
+        // The prune record and the two remaining versions of page 1.
+        assert_eq!(3 * batch_size(1) as u64, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn torn_record() {
+        let path = data_file("versioned_torn");
+        {
+            let mut dm = VersionedDiskManager::open(&path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_page(&page(1, b"complete")).unwrap();
+            dm.write_page(&page(1, b"torn    ")).unwrap();
+        }
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(3 * batch_size(1) as u64 - 1).unwrap();
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(2, dm.version());
+        assert_eq!(*b"complete", read(&mut dm, 1).unwrap().data);
+        dm.write_page(&page(1, b"after   ")).unwrap();
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(vec![1, 2, 3], dm.versions(1));
+        assert_eq!(*b"after   ", read(&mut dm, 1).unwrap().data);
+    }
+
+    #[test]
+    fn torn_batch() {
+        let path = data_file("versioned_torn_batch");
+        {
+            let mut dm = VersionedDiskManager::open(&path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_pages(&[&page(1, b"batch   "), &page(2, b"batch   ")])
+                .unwrap();
+        }
+        // Only the first page of the batch made it to disk.
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len((2 * batch_size(1) + BATCH_HEADER_SIZE + ENTRY_SIZE) as u64)
+            .unwrap();
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(2, dm.version());
+        assert_eq!(vec![1], dm.versions(1));
+        assert_eq!([0; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!([0; 8], read(&mut dm, 2).unwrap().data);
+    }
+
+    #[test]
+    fn damaged_batch() {
+        let path = data_file("versioned_damaged");
+        {
+            let mut dm = VersionedDiskManager::open(&path).unwrap();
+            dm.allocate_page().unwrap();
+            dm.write_page(&page(1, b"middle  ")).unwrap();
+            dm.write_page(&page(1, b"last    ")).unwrap();
+        }
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[batch_size(1) + BATCH_HEADER_SIZE + 5] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        // Everything from the damaged batch on is cut off.
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(1, dm.version());
+        assert_eq!([0; 8], read(&mut dm, 1).unwrap().data);
+        assert_eq!(batch_size(1) as u64, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn buffer_pool_manager() {
+        let path = data_file("versioned_bpm");
+        let mut bpm = BufferPoolManager::new(VersionedDiskManager::open(&path).unwrap());
+        bpm.new_page().unwrap().data = *b"version1";
+        bpm.flush_page(1).unwrap();
+        bpm.fetch_page(1).unwrap().data = *b"version2";
+        bpm.flush_page(1).unwrap();
+        drop(bpm);
+
+        let mut dm = VersionedDiskManager::open(&path).unwrap();
+        assert_eq!(vec![1, 2, 3], dm.versions(1));
+        assert_eq!(*b"version1", read_as_of(&mut dm, 1, 2));
+        assert_eq!(*b"version2", read_as_of(&mut dm, 1, 3));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod buffer_pool;
+pub mod server;
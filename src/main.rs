@@ -1,8 +1,85 @@
-mod buffer_pool;
-mod server;
+use buffer_pool::buffer_pool::{
+    ArcReplacer, ClockReplacer, DiskManager, DiskManagerMock, FileDiskManager, LruKReplacer,
+    LruReplacer, ObjectStoreDiskManager, PageError, Replacer, TwoQueueReplacer, MAX_POOL_SIZE,
+};
+use buffer_pool::server::serve;
+use std::env;
+use std::io::ErrorKind;
+use std::process;
+use url::Url;
 
-use crate::server::serve;
+// The last path segment of the URL is the bucket, e.g. `http://127.0.0.1:9000/pages`.
+fn open_object_store(url: &str) -> Result<Box<ObjectStoreDiskManager>, PageError> {
+    let mut url = Url::parse(url).map_err(|_| PageError::IoError(ErrorKind::InvalidInput))?;
+    let bucket = url
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+        .map(str::to_owned);
+    match bucket {
+        Some(bucket) if url.host_str().is_some() => {
+            url.set_query(None);
+            url.set_fragment(None);
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.pop_if_empty().pop();
+            }
+            ObjectStoreDiskManager::open(url.as_str(), &bucket)
+        }
+        _ => Err(PageError::IoError(ErrorKind::InvalidInput)),
+    }
+}
+
+// LRU-K is selected with its K, e.g. `lru-2`. 2Q uses the queue sizes suggested by its paper, a
+// quarter of the pool for A1in and half of it for A1out.
+fn open_replacer(name: &str) -> Option<Box<dyn Replacer + Send>> {
+    match name {
+        "clock" => Some(Box::new(ClockReplacer::new())),
+        "lru" => Some(Box::new(LruReplacer::new())),
+        "arc" => Some(Box::new(ArcReplacer::new(MAX_POOL_SIZE))),
+        "2q" => Some(Box::new(TwoQueueReplacer::new(
+            MAX_POOL_SIZE / 4,
+            MAX_POOL_SIZE / 2,
+        ))),
+        _ => match name.strip_prefix("lru-").map(str::parse::<usize>) {
+            Some(Ok(k)) if k > 0 => Some(Box::new(LruKReplacer::new(k, 0))),
+            _ => None,
+        },
+    }
+}
 
 fn main() {
-    serve()
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut replacer: Box<dyn Replacer + Send> = Box::new(ClockReplacer::new());
+    if let Some(index) = args.iter().position(|arg| arg == "--replacer") {
+        let name = args.get(index + 1).cloned().unwrap_or_default();
+        replacer = match open_replacer(&name) {
+            Some(replacer) => replacer,
+            None => {
+                eprintln!(
+                    "unknown replacer {:?}, expected clock, lru, lru-<k>, 2q or arc",
+                    name
+                );
+                process::exit(2);
+            }
+        };
+        args.drain(index..(index + 2).min(args.len()));
+    }
+
+    let disk_manager: Box<dyn DiskManager + Send> = match args.into_iter().next() {
+        Some(url) if url.starts_with("http://") => match open_object_store(&url) {
+            Ok(disk_manager) => disk_manager,
+            Err(e) => {
+                eprintln!("unable to open object store {}: {}", url, e);
+                process::exit(1);
+            }
+        },
+        Some(path) => match FileDiskManager::open(&path) {
+            Ok(disk_manager) => disk_manager,
+            Err(e) => {
+                eprintln!("unable to open data file {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => DiskManagerMock::new(),
+    };
+    serve(disk_manager, replacer)
 }
@@ -1,4 +1,4 @@
-use crate::buffer_pool::{BufferPoolManager, DiskManagerMock};
+use crate::buffer_pool::{BufferPoolManager, DiskManager, PageError, Replacer};
 use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
@@ -38,7 +38,7 @@ fn add_headers(response: &mut Response<Body>) {
 fn new_page(bpm: Arc<Mutex<BufferPoolManager>>, response: &mut Response<Body>) {
     add_headers(response);
     let mut m = bpm.lock().unwrap();
-    if let Err(_) = m.new_page() {
+    if m.new_page().is_err() {
         make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
     } else {
         *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
@@ -53,7 +53,7 @@ fn flush_page(
     add_headers(response);
     let mut m = bpm.lock().unwrap();
     if let Some(page) = page_param(req) {
-        if let Err(_) = m.flush_page(page) {
+        if m.flush_page(page).is_err() {
             make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
         } else {
             *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
@@ -71,7 +71,7 @@ fn delete_page(
     add_headers(response);
     let mut m = bpm.lock().unwrap();
     if let Some(page) = page_param(req) {
-        if let Err(_) = m.delete_page(page) {
+        if m.delete_page(page).is_err() {
             make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
         } else {
             *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
@@ -89,7 +89,7 @@ fn unpin_page(
     add_headers(response);
     let mut m = bpm.lock().unwrap();
     if let Some(page) = page_param(req) {
-        if let Err(_) = m.unpin_page(page, false) {
+        if m.unpin_page(page, false).is_err() {
             make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
         } else {
             *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
@@ -107,7 +107,7 @@ fn fetch_page(
     add_headers(response);
     let mut m = bpm.lock().unwrap();
     if let Some(page) = page_param(req) {
-        if let Err(_) = m.fetch_page(page) {
+        if m.fetch_page(page).is_err() {
             make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
         } else {
             *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
@@ -120,25 +120,30 @@ fn fetch_page(
 fn flush_all(bpm: Arc<Mutex<BufferPoolManager>>, response: &mut Response<Body>) {
     add_headers(response);
     let mut m = bpm.lock().unwrap();
-    if let Err(_) = m.flush_all_pages() {
+    if m.flush_all_pages().is_err() {
         make_error(StatusCode::INTERNAL_SERVER_ERROR, response);
     } else {
         *response.body_mut() = Body::from(serde_json::to_string(&m.response()).unwrap());
     }
 }
 
+// Writes back the pages that were unpinned dirty but never flushed, and makes them durable.
+fn close(bpm: &Mutex<BufferPoolManager>) -> Result<(), PageError> {
+    bpm.lock().unwrap().sync()
+}
+
 async fn shutdown_signal() {
     tokio::signal::ctrl_c()
         .await
         .expect("failed to install CTRL+C signal handler");
 }
 
-pub fn serve() {
+pub fn serve(disk_manager: Box<dyn DiskManager + Send>, replacer: Box<dyn Replacer + Send>) {
     let rt = runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
-    rt.block_on(run_server());
+    rt.block_on(run_server(disk_manager, replacer));
 }
 
 async fn route(
@@ -162,11 +167,15 @@ async fn route(
     Ok(response)
 }
 
-async fn run_server() {
+async fn run_server(disk_manager: Box<dyn DiskManager + Send>, replacer: Box<dyn Replacer + Send>) {
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
 
-    let shared = Arc::new(Mutex::new(BufferPoolManager::new(DiskManagerMock::new())));
+    let shared = Arc::new(Mutex::new(BufferPoolManager::with_replacer(
+        disk_manager,
+        replacer,
+    )));
 
+    let bpm = shared.clone();
     let svc = make_service_fn(move |_| {
         let local = shared.clone();
         async { Ok::<_, Infallible>(service_fn(move |req| route(req, local.clone()))) }
@@ -178,4 +187,36 @@ async fn run_server() {
     if let Err(e) = graceful.await {
         eprintln!("server error: {}", e);
     }
+    if let Err(e) = close(&bpm) {
+        eprintln!("unable to flush pages: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::tests::data_file;
+    use crate::buffer_pool::{BufferPoolManager, FileDiskManager};
+    use crate::server::close;
+    use std::sync::Mutex;
+
+    #[test]
+    fn flush_on_close() {
+        let path = data_file("server_close");
+        let bpm = Mutex::new(BufferPoolManager::new(
+            FileDiskManager::open(&path).unwrap(),
+        ));
+        let id = {
+            let mut m = bpm.lock().unwrap();
+            let page = m.new_page().unwrap();
+            page.data = *b"unpinned";
+            let id = page.id();
+            m.unpin_page(id, true).unwrap();
+            id
+        };
+        close(&bpm).unwrap();
+        drop(bpm);
+
+        let mut bpm = BufferPoolManager::new(FileDiskManager::open(&path).unwrap());
+        assert_eq!(*b"unpinned", bpm.fetch_page(id).unwrap().data);
+    }
 }